@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS commands (
+    id              SERIAL PRIMARY KEY,
+    name            TEXT NOT NULL UNIQUE,
+    url             TEXT NOT NULL,
+    owner           BIGINT NOT NULL,
+    stat            INTEGER NOT NULL DEFAULT 0,
+    created         BIGINT NOT NULL,
+    last_used       BIGINT,
+    edited_by       BIGINT,
+    edited          BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS commands_owner_idx ON commands (owner);
+CREATE INDEX IF NOT EXISTS commands_stat_idx ON commands (stat DESC);
+
+CREATE TABLE IF NOT EXISTS servers (
+    id              BIGINT PRIMARY KEY,
+    prefix          TEXT NOT NULL
+);
@@ -0,0 +1,6 @@
+ALTER TABLE commands ADD COLUMN trigger_kind TEXT NOT NULL DEFAULT 'prefix';
+ALTER TABLE commands ADD COLUMN pattern TEXT;
+
+CREATE TABLE IF NOT EXISTS disabled_trigger_channels (
+    id              BIGINT PRIMARY KEY
+);
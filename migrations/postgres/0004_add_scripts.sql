@@ -0,0 +1 @@
+ALTER TABLE commands ADD COLUMN script TEXT;
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS guild_languages (
+    id              BIGINT PRIMARY KEY,
+    language        TEXT NOT NULL
+);
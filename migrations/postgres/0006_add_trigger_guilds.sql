@@ -0,0 +1 @@
+ALTER TABLE commands ADD COLUMN trigger_guild BIGINT;
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serenity;
+use serenity::CACHE;
+use serenity::prelude::Context;
+use serenity::Error as SerenityError;
+use serenity::model::{ChannelId, EmojiId, Message, ModelError, ReactionType, UserId};
+use store::CustomCommand;
+use helpers;
+use strings;
+use strings::Text;
+use script;
+use config;
+
+pub const MAX_REACTIONS: usize = 5;
+
+// owners are told about a failing command at most this often
+const REPORT_COOLDOWN_SECS: u64 = 60 * 60;
+
+lazy_static! {
+    // when the owner was last told about a failure, by command name and action
+    static ref REPORTED: Mutex<HashMap<(String, strings::Error), Instant>> = Mutex::new(HashMap::new());
+}
+
+/// Parses a unicode emoji or a custom emoji like `<:name:id>`
+pub fn parse_reaction(emoji: &str) -> ReactionType {
+    let custom = emoji.trim_left_matches("<a:").trim_left_matches("<:").trim_right_matches('>');
+
+    if custom.len() < emoji.len() {
+        let mut parts = custom.rsplitn(2, ':');
+
+        if let (Some(id), Some(name)) = (parts.next(), parts.next()) {
+            if let Ok(id) = id.parse::<u64>() {
+                return ReactionType::Custom {
+                    id: EmojiId(id),
+                    name: Some(name.to_string()),
+                };
+            }
+        }
+    }
+
+    ReactionType::Unicode(emoji.to_string())
+}
+
+// checks if a channel is in the guild the message was sent in, so commands,
+// which are shared between guilds, can't be used to post in other guilds
+fn in_same_guild(msg: &Message, channel: ChannelId) -> bool {
+    let guild_id = match msg.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return false,
+    };
+
+    let cache = CACHE.read().unwrap();
+
+    match cache.guild_channel(channel) {
+        Some(channel) => channel.read().unwrap().guild_id == guild_id,
+        None => false,
+    }
+}
+
+fn is_permission_error(why: &SerenityError) -> bool {
+    match *why {
+        SerenityError::Model(ModelError::InvalidPermissions(_)) => true,
+        SerenityError::Http(serenity::http::HttpError::UnsuccessfulRequest(ref response)) => {
+            response.status.to_u16() == 403
+        },
+        _ => false,
+    }
+}
+
+/// Tells the owner of a command that an action failed because the bot is missing
+/// permissions, other failures are only logged
+pub fn failed(msg: &Message, command: &CustomCommand, action: strings::Error, why: SerenityError) {
+    if !is_permission_error(&why) {
+        return error!("Action {} of command {} failed: {:?}", action.key(), command.name, why);
+    }
+
+    warn!("Missing permissions for action {} of command {} in channel {}",
+          action.key(), command.name, msg.channel_id);
+
+    {
+        let mut reported = REPORTED.lock().unwrap();
+        let key = (command.name.clone(), action);
+
+        if let Some(last) = reported.get(&key) {
+            if last.elapsed() < Duration::from_secs(REPORT_COOLDOWN_SECS) {
+                return;
+            }
+        }
+
+        reported.insert(key, Instant::now());
+    }
+
+    let report = helpers::get_error_f(strings::Error::ActionMissingPermissions, &[
+        ("name", &command.name),
+        ("action", &helpers::get_error(action)),
+        ("channel", &msg.channel_id.0),
+    ]);
+
+    let owner = UserId(command.owner as u64);
+    if let Err(why) = owner.create_dm_channel().and_then(|channel| channel.say(report)) {
+        error!("Failed to tell the owner of {} about missing permissions: {:?}", command.name, why);
+    }
+}
+
+// where the response of a command is sent, with the action reported if sending fails
+fn target(msg: &Message, command: &CustomCommand) -> (strings::Error, serenity::Result<ChannelId>) {
+    let actions = &command.actions;
+
+    if actions.dm {
+        return (strings::Error::ActionDm, msg.author.create_dm_channel().map(|channel| channel.id));
+    }
+
+    match actions.channel.map(ChannelId) {
+        Some(channel) if in_same_guild(msg, channel) => (strings::Error::ActionChannel, Ok(channel)),
+        _ => (strings::Error::ActionReply, Ok(msg.channel_id)),
+    }
+}
+
+/// Sends the response or script of a command and runs its actions, `render`
+/// fills in the placeholders of the text sent
+pub fn run<F: Fn(&str) -> String>(ctx: &Context, msg: &Message, command: &CustomCommand, render: F) {
+    let actions = &command.actions;
+
+    for reaction in &actions.reactions {
+        if let Err(why) = msg.react(parse_reaction(reaction)) {
+            failed(msg, command, strings::Error::ActionReact, why);
+        }
+    }
+
+    if !actions.silent {
+        let (action, channel) = target(msg, command);
+
+        let sent = channel.and_then(|channel| match command.script {
+            Some(ref steps) => {
+                if !script::start(ctx, msg, command, channel, script::render(steps, &render)) {
+                    let prefix = config::get(ctx).discord.prefix.clone();
+                    let _ = msg.channel_id.say(helpers::get_error_f(strings::Error::ScriptTooMany,
+                                                                    &[("prefix", &prefix)]));
+                }
+
+                Ok(())
+            },
+            None => channel.say(render(&command.url)).map(|_| ()),
+        });
+
+        if let Err(why) = sent {
+            failed(msg, command, action, why);
+        }
+    }
+
+    if actions.delete_trigger {
+        if let Err(why) = msg.delete() {
+            failed(msg, command, strings::Error::ActionDelete, why);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use chrono::prelude::*;
+use error::{Error, Result};
+use store::CommandStore;
+use helpers;
+use strings;
+
+const BACKUP_DIR: &'static str = "backups";
+const RETENTION: usize = 7;
+
+const PREFIX: &'static str = "backup-";
+const EXTENSION: &'static str = ".sqlite3";
+
+/// Where backups are written and how many are kept, the `[backup]` section of the config
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub dir: PathBuf,
+    /// Number of backups kept, older ones are deleted after each backup
+    pub retention: usize,
+    /// Hours between automatic backups, 0 disables them
+    pub interval: u64
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            dir: PathBuf::from(BACKUP_DIR),
+            retention: RETENTION,
+            interval: 0
+        }
+    }
+}
+
+// file name of a backup made at a time, with milliseconds so a scheduled backup
+// and a manual one in the same second don't overwrite each other
+fn file_name(time: DateTime<Utc>) -> String {
+    // the nanoseconds go past a second during leap seconds
+    let millis = ::std::cmp::min(time.nanosecond() / 1_000_000, 999);
+
+    format!("{}{}-{:03}{}", PREFIX, time.format("%Y%m%d-%H%M%S"), millis, EXTENSION)
+}
+
+/// Writes a timestamped backup of the store, returning its file name
+pub fn create(store: &CommandStore, settings: &Settings) -> Result<String> {
+    try!(fs::create_dir_all(&settings.dir));
+
+    let name = file_name(Utc::now());
+    try!(store.backup(&settings.dir.join(&name)));
+
+    try!(prune(settings));
+
+    Ok(name)
+}
+
+/// File names of existing backups, oldest first
+pub fn list(settings: &Settings) -> Result<Vec<String>> {
+    if !settings.dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in try!(fs::read_dir(&settings.dir)) {
+        let name = try!(entry).file_name().to_string_lossy().into_owned();
+
+        if name.starts_with(PREFIX) && name.ends_with(EXTENSION) {
+            backups.push(name);
+        }
+    }
+
+    // timestamps in the names sort chronologically
+    backups.sort();
+
+    Ok(backups)
+}
+
+// deletes the oldest backups beyond the retention limit
+fn prune(settings: &Settings) -> Result<()> {
+    let backups = try!(list(settings));
+
+    if backups.len() <= settings.retention {
+        return Ok(());
+    }
+
+    for name in &backups[..backups.len() - settings.retention] {
+        info!("Deleting old backup {}", name);
+        try!(fs::remove_file(settings.dir.join(name)));
+    }
+
+    Ok(())
+}
+
+/// Replaces the contents of the store with the backup with the given file name
+pub fn restore(store: &CommandStore, settings: &Settings, name: &str) -> Result<()> {
+    // only allow restoring backups from the backup directory
+    if Path::new(name).file_name().map_or(true, |file_name| file_name != name) ||
+        !try!(list(settings)).iter().any(|backup| backup == name) {
+        return Err(Error::invalid_input(helpers::get_error_f(strings::Error::BackupNotFound, &[("name", &name)])));
+    }
+
+    store.restore(&settings.dir.join(name))
+}
+
+/// Starts a thread creating backups every `interval` hours, if enabled
+pub fn schedule(store: Arc<CommandStore>, settings: Settings) {
+    if settings.interval == 0 {
+        return;
+    }
+
+    info!("Backing up the database to {} every {} hours, keeping {} backups",
+          settings.dir.display(), settings.interval, settings.retention);
+
+    thread::spawn(move || {
+        let interval = Duration::from_secs(settings.interval * 60 * 60);
+
+        loop {
+            thread::sleep(interval);
+
+            match create(&*store, &settings) {
+                Ok(name) => info!("Created scheduled backup {}", name),
+                Err(why) => error!("Failed to create scheduled backup: {}", why),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+    use super::*;
+    use sqlite;
+
+    // an empty directory only used by one test
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("backup-test-{}-{}", process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn settings(dir: PathBuf, retention: usize) -> Settings {
+        Settings {
+            dir: dir,
+            retention: retention,
+            interval: 0
+        }
+    }
+
+    #[test]
+    fn backups_round_trip() {
+        let settings = settings(temp_dir("round-trip"), 7);
+        let store = sqlite::open_in_memory().unwrap();
+        store.add("hello", "world", 1).unwrap();
+
+        let name = create(&store, &settings).unwrap();
+        assert_eq!(list(&settings).unwrap(), vec![name.clone()]);
+
+        store.delete_all().unwrap();
+        store.add("other", "url", 2).unwrap();
+
+        restore(&store, &settings, &name).unwrap();
+        assert_eq!(store.get("hello").unwrap().url, "world");
+        assert!(!store.is_command("other").unwrap());
+
+        let _ = fs::remove_dir_all(&settings.dir);
+    }
+
+    #[test]
+    fn pruning_deletes_the_oldest_backups() {
+        let settings = settings(temp_dir("prune"), 2);
+        let names = ["backup-20240101-000000-000.sqlite3", "backup-20240102-000000-000.sqlite3",
+                     "backup-20240102-000000-001.sqlite3", "backup-20240103-120000-000.sqlite3"];
+
+        for name in &names {
+            fs::write(settings.dir.join(name), b"").unwrap();
+        }
+        // other files in the directory are left alone
+        fs::write(settings.dir.join("notes.txt"), b"").unwrap();
+
+        prune(&settings).unwrap();
+
+        assert_eq!(list(&settings).unwrap(), vec![names[2], names[3]]);
+        assert!(settings.dir.join("notes.txt").is_file());
+
+        let _ = fs::remove_dir_all(&settings.dir);
+    }
+
+    #[test]
+    fn backups_in_the_same_second_have_different_names() {
+        let time = Utc.ymd(2024, 1, 2).and_hms_milli(3, 4, 5, 6);
+
+        assert_eq!(file_name(time), "backup-20240102-030405-006.sqlite3");
+        assert_ne!(file_name(time), file_name(Utc.ymd(2024, 1, 2).and_hms_milli(3, 4, 5, 7)));
+        assert!(file_name(time) < file_name(Utc.ymd(2024, 1, 2).and_hms_milli(3, 4, 5, 10)));
+    }
+
+    #[test]
+    fn only_listed_backups_are_restored() {
+        let settings = settings(temp_dir("restore-names"), 7);
+        let store = sqlite::open_in_memory().unwrap();
+        fs::write(settings.dir.join("other.sqlite3"), b"").unwrap();
+
+        for name in &["backup-20240101-000000-000.sqlite3", "other.sqlite3", "../backup-x.sqlite3", ""] {
+            assert!(restore(&store, &settings, name).is_err(), "restored {}", name);
+        }
+
+        let _ = fs::remove_dir_all(&settings.dir);
+    }
+}
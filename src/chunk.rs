@@ -0,0 +1,408 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Max characters in a message
+pub const MESSAGE_LIMIT: usize = 2000;
+/// Max characters in an embed description
+pub const EMBED_DESCRIPTION_LIMIT: usize = 2048;
+/// Max characters in an embed field value
+pub const EMBED_FIELD_LIMIT: usize = 1024;
+/// Max characters in an embed title
+pub const EMBED_TITLE_LIMIT: usize = 256;
+
+const FENCE: &'static str = "```";
+
+fn char_len(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Splits text into chunks of at most `limit` characters.
+///
+/// Text is split on line boundaries where possible, then on word boundaries and
+/// finally on grapheme boundaries for single words that are too long.  Code blocks
+/// that are split between chunks are closed at the end of a chunk and reopened
+/// with the same language at the start of the next one.
+pub fn chunk(text: &str, limit: usize) -> Vec<String> {
+    let mut chunker = Chunker::new(limit);
+
+    for line in text.lines() {
+        chunker.push_line(line);
+    }
+
+    chunker.finish()
+}
+
+/// Wraps text in a code block with the given language and splits it into chunks.
+pub fn chunk_code_block(text: &str, lang: &str, limit: usize) -> Vec<String> {
+    chunk(&format!("{}{}\n{}\n{}", FENCE, lang, text, FENCE), limit)
+}
+
+/// Cuts text down to at most `limit` characters, ending it with an ellipsis if shortened.
+pub fn truncate(text: &str, limit: usize) -> String {
+    if char_len(text) <= limit {
+        return text.to_string();
+    }
+
+    let mut truncated = String::new();
+    let mut len = 0;
+
+    for grapheme in text.graphemes(true) {
+        let grapheme_len = char_len(grapheme);
+
+        if len + grapheme_len + 1 > limit {
+            break;
+        }
+
+        truncated.push_str(grapheme);
+        len += grapheme_len;
+    }
+
+    truncated.push('…');
+    truncated
+}
+
+struct Chunker {
+    limit: usize,
+    chunks: Vec<String>,
+    current: String,
+    current_len: usize,
+    // number of lines of content in the current chunk, not counting a reopened fence
+    lines: usize,
+    // opening line of the code block we are currently in, if any
+    fence: Option<String>,
+}
+
+impl Chunker {
+    fn new(limit: usize) -> Chunker {
+        Chunker {
+            limit: limit,
+            chunks: Vec::new(),
+            current: String::new(),
+            current_len: 0,
+            lines: 0,
+            fence: None,
+        }
+    }
+
+    fn push_line(&mut self, line: &str) {
+        // fences toggle with every odd number of ``` on a line
+        let toggles = line.matches(FENCE).count() % 2 == 1;
+        let opens = toggles && self.fence.is_none();
+
+        let fence = if opens {
+            Some(opening_fence(line))
+        } else {
+            self.fence.clone()
+        };
+
+        // leave room to reopen and close a code block in every chunk
+        let overhead = fence.as_ref().map_or(0, |f| char_len(f) + 1 + FENCE.len() + 1);
+        let max = if self.limit > overhead + 1 {
+            self.limit - overhead
+        } else {
+            1
+        };
+
+        for piece in split_line(line, max) {
+            self.push_piece(&piece, fence.is_some());
+        }
+
+        if toggles {
+            self.fence = if opens { fence } else { None };
+        }
+    }
+
+    fn push_piece(&mut self, piece: &str, in_fence: bool) {
+        let piece_len = char_len(piece);
+        let separator = if self.current.is_empty() { 0 } else { 1 };
+        let closing = if in_fence { FENCE.len() + 1 } else { 0 };
+
+        // will overflow, move to next chunk
+        if self.lines > 0 && self.current_len + separator + piece_len + closing > self.limit {
+            self.flush();
+        }
+
+        if !self.current.is_empty() {
+            self.current.push('\n');
+            self.current_len += 1;
+        }
+
+        self.current.push_str(piece);
+        self.current_len += piece_len;
+        self.lines += 1;
+    }
+
+    fn flush(&mut self) {
+        if self.fence.is_some() {
+            self.current.push('\n');
+            self.current.push_str(FENCE);
+        }
+
+        self.chunks.push(self.current.clone());
+        self.current.clear();
+        self.current_len = 0;
+        self.lines = 0;
+
+        // continue the code block in the next chunk
+        if let Some(ref fence) = self.fence {
+            self.current.push_str(fence);
+            self.current_len = char_len(fence);
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        if self.lines > 0 {
+            // close any code block left open by the text itself
+            if self.fence.is_some() {
+                self.current.push('\n');
+                self.current.push_str(FENCE);
+            }
+
+            self.chunks.push(self.current);
+        }
+
+        self.chunks
+    }
+}
+
+// the ``` and language of a line opening a code block
+fn opening_fence(line: &str) -> String {
+    let trimmed = line.trim_left();
+
+    if trimmed.starts_with(FENCE) {
+        trimmed.split_whitespace().next().unwrap_or(FENCE).to_string()
+    } else {
+        FENCE.to_string()
+    }
+}
+
+// splits a line into pieces of at most `max` characters on word boundaries,
+// falling back to grapheme boundaries for words that are too long
+fn split_line(line: &str, max: usize) -> Vec<String> {
+    if char_len(line) <= max {
+        return vec![line.to_string()];
+    }
+
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut len = 0;
+
+    for word in line.split_word_bounds() {
+        let word_len = char_len(word);
+
+        if len + word_len <= max {
+            current.push_str(word);
+            len += word_len;
+            continue;
+        }
+
+        if len > 0 {
+            pieces.push(current.trim_right().to_string());
+            current.clear();
+            len = 0;
+        }
+
+        // don't start a piece with the whitespace we broke on
+        if word.trim().is_empty() {
+            continue;
+        }
+
+        if word_len <= max {
+            current.push_str(word);
+            len = word_len;
+            continue;
+        }
+
+        for grapheme in word.graphemes(true) {
+            let grapheme_len = char_len(grapheme);
+
+            if len > 0 && len + grapheme_len > max {
+                pieces.push(current.clone());
+                current.clear();
+                len = 0;
+            }
+
+            current.push_str(grapheme);
+            len += grapheme_len;
+        }
+    }
+
+    if len > 0 {
+        pieces.push(current);
+    }
+
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FAMILY: &'static str = "👨‍👩‍👧";
+
+    // small deterministic generator so failures can be reproduced
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, max: usize) -> usize {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 33) as usize) % max
+        }
+    }
+
+    const WORDS: &'static [&'static str] = &[
+        "a", "command", "é", "コマンド", FAMILY, "🦀🦀", "supercalifragilisticexpialidocious",
+        "https://example.com/a/very/long/url/that/goes/on/and/on", "-", "\t",
+    ];
+
+    fn random_text(rng: &mut Rng, code: bool) -> String {
+        let mut lines = Vec::new();
+        let mut in_fence = false;
+
+        for _ in 0..rng.next(60) + 1 {
+            if code && rng.next(8) == 0 {
+                lines.push(if in_fence { "```".to_string() } else { "```rust".to_string() });
+                in_fence = !in_fence;
+                continue;
+            }
+
+            let words: Vec<&str> = (0..rng.next(30)).map(|_| WORDS[rng.next(WORDS.len())]).collect();
+            lines.push(words.join(" "));
+        }
+
+        if in_fence {
+            lines.push("```".to_string());
+        }
+
+        lines.join("\n")
+    }
+
+    fn without_whitespace(text: &str) -> String {
+        text.chars().filter(|c| !c.is_whitespace()).collect()
+    }
+
+    // the text without fence lines, which are added when a code block is split
+    fn without_fences(text: &str) -> String {
+        let lines: Vec<&str> = text.lines().filter(|line| !line.trim_left().starts_with(FENCE)).collect();
+        without_whitespace(&lines.join("\n"))
+    }
+
+    fn fences_balanced(chunk: &str) -> bool {
+        chunk.lines().filter(|line| line.matches(FENCE).count() % 2 == 1).count() % 2 == 0
+    }
+
+    #[test]
+    fn chunks_are_within_the_limit() {
+        let mut rng = Rng(1);
+
+        for &limit in &[20, 50, 100, EMBED_FIELD_LIMIT, MESSAGE_LIMIT] {
+            for _ in 0..50 {
+                let text = random_text(&mut rng, true);
+
+                for chunk in chunk(&text, limit) {
+                    assert!(char_len(&chunk) <= limit, "{} > {}: {:?}", char_len(&chunk), limit, chunk);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn code_fences_stay_balanced() {
+        let mut rng = Rng(2);
+
+        for &limit in &[30, 100, MESSAGE_LIMIT] {
+            for _ in 0..50 {
+                let text = random_text(&mut rng, true);
+
+                for chunk in chunk(&text, limit) {
+                    assert!(fences_balanced(&chunk), "unbalanced fences: {:?}", chunk);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn content_is_preserved() {
+        let mut rng = Rng(3);
+
+        for &limit in &[20, 100, MESSAGE_LIMIT] {
+            for _ in 0..50 {
+                let text = random_text(&mut rng, false);
+                assert_eq!(without_whitespace(&chunk(&text, limit).concat()), without_whitespace(&text));
+
+                let code = random_text(&mut rng, true);
+                assert_eq!(without_fences(&chunk(&code, limit).join("\n")), without_fences(&code));
+            }
+        }
+    }
+
+    #[test]
+    fn short_lines_are_kept_whole() {
+        let text = (0..300).map(|i| format!("line {}", i)).collect::<Vec<_>>().join("\n");
+        let chunks = chunk(&text, 100);
+
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.join("\n"), text);
+    }
+
+    #[test]
+    fn code_blocks_are_reopened_with_their_language() {
+        let text = format!("```rust\n{}\n```", vec!["let x = 1;"; 50].join("\n"));
+        let chunks = chunk(&text, 100);
+
+        assert!(chunks.len() > 1);
+
+        for chunk in &chunks {
+            assert!(chunk.starts_with("```rust\n"));
+            assert!(chunk.ends_with("\n```"));
+        }
+    }
+
+    #[test]
+    fn chunk_code_block_wraps_the_text() {
+        assert_eq!(chunk_code_block("a\nb", "json", 100), vec!["```json\na\nb\n```".to_string()]);
+    }
+
+    #[test]
+    fn empty_text_has_no_chunks() {
+        assert!(chunk("", 100).is_empty());
+    }
+
+    #[test]
+    fn long_words_are_split() {
+        let word = "a".repeat(45);
+        assert_eq!(split_line(&word, 10), vec!["a".repeat(10), "a".repeat(10), "a".repeat(10),
+                                               "a".repeat(10), "a".repeat(5)]);
+
+        // breaks between words rather than inside them when possible
+        assert_eq!(split_line("hello there world", 12), vec!["hello there", "world"]);
+    }
+
+    #[test]
+    fn graphemes_are_not_split() {
+        // each family is a single grapheme of 5 characters
+        let word = FAMILY.repeat(7);
+
+        for piece in split_line(&word, 12) {
+            assert!(char_len(&piece) <= 12);
+            assert_eq!(piece.replace(FAMILY, ""), "");
+        }
+
+        let pieces = split_line(&format!("é{}", "e\u{301}".repeat(20)), 5);
+        assert!(pieces.iter().all(|piece| !piece.starts_with('\u{301}')));
+        assert_eq!(pieces.concat(), format!("é{}", "e\u{301}".repeat(20)));
+    }
+
+    #[test]
+    fn truncate_fits_the_limit() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly 10", 10), "exactly 10");
+
+        let truncated = truncate("this is a little too long", 10);
+        assert_eq!(truncated, "this is a…");
+        assert_eq!(char_len(&truncated), 10);
+
+        let truncated = truncate(&FAMILY.repeat(5), 12);
+        assert!(char_len(&truncated) <= 12);
+        assert_eq!(truncated, format!("{}…", FAMILY.repeat(2)));
+    }
+}
@@ -1,15 +1,30 @@
-use sqlite;
+use store;
+use store::{Step, TriggerKind};
+use commands::is_reserved;
 use std::fmt::Write;
-use serenity::model::Message;
+use serenity::model::{ChannelId, Message};
+use error;
+use error::Error;
 use chrono::prelude::*;
-use std::env;
 use serde_json;
 use serde_json::Value;
 use serde_json::Map;
 use helpers;
+use strings;
+use pagination;
+use chunk;
+use trigger;
+use config;
+use actions;
+use script;
 
+// max characters per page in paginated lists, well below the embed description limit
+const PAGE_LENGTH: usize = 1000;
 
-fn has_permission(msg: &Message) -> bool {
+// responses ending in these are shown as an image preview in stats
+const IMAGE_EXTENSIONS: &'static [&'static str] = &[".png", ".jpg", ".jpeg", ".gif", ".webp"];
+
+pub fn has_permission(msg: &Message) -> bool {
     let guild = match msg.guild() {
         Some(guild) => guild,
         None => {
@@ -33,286 +48,401 @@ fn has_permission(msg: &Message) -> bool {
     }
 }
 
-// splits a string that might be too long
-fn split_message(msg: &str, prepend: Option<&str>, with_code_block: bool) -> Vec<String> {
-    let split = msg.split("\n");
-    let mut vec = Vec::new();
-    let mut single_msg = String::new();
-
-    // add text in beginning before code blocks
-    match prepend {
-        Some(val) => {
-            single_msg.push_str(&val);
-        },
-        None => {},
-    };
+command!(commands(ctx, msg, _args) {
+    let commands = try_reply!(msg, store::get(ctx).all());
 
-    if with_code_block {
-        single_msg.push_str("\n```"); // add starting code block
+    let mut contents = String::new();
+    for cmd in commands {
+        let _ = write!(contents, "{}\n", cmd.name);
     }
 
-    for s in split {
-        single_msg.push_str("\n"); // re-add new line at end
+    let pages = chunk::chunk_code_block(contents.trim_right(), "", PAGE_LENGTH);
+    pagination::send(ctx, msg, &helpers::get_info(strings::Info::CommandsTitle), pages);
+});
 
-        // will overflow, move to next msg (in bytes) + 6 just in case?
-        if single_msg.len() + s.len() + 6 > 4000 {
-            // add closing code block
-            if with_code_block {
-                single_msg.push_str("```");
-            }
+command!(top(ctx, msg, _args) {
+    let db = store::get(ctx);
 
-            vec.push(single_msg.clone());   // push the full string to vec
-            single_msg.clear();     // reset single message
+    let commands = try_reply!(msg, db.top());
 
-            // start new code block 
-            if with_code_block {
-                single_msg.push_str("```\n");
-            }
-        }
+    let mut contents = format!("```{}\n", helpers::get_info(strings::Info::TopTitle));
+
+    let commands = commands.iter().take(10);
 
-        // append the next line
-        single_msg.push_str(s);
+    for cmd in commands {
+        let _ = write!(contents, "{} - {}\n", cmd.stat, cmd.name);
     }
 
-    // push the remaining string
-    if !single_msg.is_empty() {
-        if with_code_block {
-            single_msg.push_str("```"); // add closing code block
+    let _ = write!(contents, "```");
+
+    let _ = msg.channel_id.say(&contents);
+});
+
+command!(add(ctx, msg, args) {
+    let config = config::get(ctx);
+    let home_guild_id = config.home_guild.id;
+    let home_guild = helpers::get_error_f(strings::Error::HomeGuild, &[("guild", &config.home_guild.name)]);
+
+    // limit command adding to the main guild
+    if let Some(guild_id) = msg.guild_id() {
+        if guild_id.0 != home_guild_id {
+            let _ = msg.channel_id.say(&home_guild);
+            return Ok(());
         }
+    } else { // return if no guild found (maybe in dms?)
+        let _ = msg.channel_id.say(&home_guild);
+        return Ok(()); 
+    }
 
-        vec.push(single_msg);
+    let name = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+
+    let url = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+
+    if is_reserved(&name) {
+        return Err(error::reply(msg, Error::Reserved(name)));
     }
 
-    vec
-}
+    let db = store::get(ctx);
 
-command!(commands(ctx, msg, _args) {
-    let mut data = ctx.data.lock();
-    let db = data.get_mut::<sqlite::Database>().unwrap();
+    try_reply!(msg, db.add(&name, &url, msg.author.id.0));
+    let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::CommandAdded,
+                                                   &[("name", &name), ("response", &url)]));
+});
 
-    let commands = try!(db.all());
 
-    let mut contents = String::new();
-    for cmd in commands {
-        let _ = write!(contents, "{}\n", cmd.name);
+command!(delete(ctx, msg, args) {
+    let name = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+
+    let db = store::get(ctx);
+
+    let cmd = try_reply!(msg, db.get(&name));
+
+    if !cmd.is_owner(msg.author.id.0) && !has_permission(msg) {
+        return Err(error::reply(msg, Error::PermissionDenied(strings::Error::CommandDeleteNoPermission)));
     }
 
-    let dm = match msg.author.create_dm_channel() {
-        Ok(val) => val,
-        Err(_) => {
-            let _ = msg.channel_id.say("Failed to send DM, maybe you don't have them enabled?");
-            return Ok(());
-        }
-    };
+    try_reply!(msg, db.delete(&name));
+    trigger::get(ctx).invalidate();
+    let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::CommandDeleted, &[("name", &name)]));
+});
+
+command!(edit(ctx, msg, args) {
+    let name = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+
+    let new_name = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
 
-    let messages = split_message(&contents, Some("Available Commands:"), true);
+    let new_url = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
 
-    for msg in messages {
-        let _ = dm.say(&msg);
+    if is_reserved(&new_name) {
+        return Err(error::reply(msg, Error::Reserved(new_name)));
     }
 
-    if !msg.is_private() {
-        let _ = msg.channel_id.say(":mailbox_with_mail: Sent you a DM with the commands list.");
+    let db = store::get(ctx);
+
+    let cmd = try_reply!(msg, db.get(&name));
+
+    // check permissions
+    if !cmd.is_owner(msg.author.id.0) && !has_permission(msg) {
+        return Err(error::reply(msg, Error::PermissionDenied(strings::Error::CommandEditNoPermission)));
     }
+
+    try_reply!(msg, db.edit(&name, &new_name, &new_url, msg.author.id.0));
+    trigger::get(ctx).invalidate();
+    let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::CommandUpdated, &[
+        ("name", &name),
+        ("new_name", &new_name),
+        ("response", &new_url),
+    ]));
 });
 
-command!(top(ctx, msg, _args) {
-    let mut data = ctx.data.lock();
-    let db = data.get_mut::<sqlite::Database>().unwrap();
+command!(set_trigger(ctx, msg, args) {
+    let name = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
 
-    let commands = try!(db.top());
+    let kind = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+    let kind = try_reply!(msg, TriggerKind::parse(&kind).ok_or_else(|| {
+        Error::invalid_input(helpers::get_error_f(strings::Error::TriggerUnknownKind, &[("kind", &kind)]))
+    }));
 
-    let mut contents = "```Top 10 Most Used Commands:\n".to_string();
+    let db = store::get(ctx);
 
-    let commands = commands.iter().take(10);
+    let cmd = try_reply!(msg, db.get(&name));
 
-    for cmd in commands {
-        let _ = write!(contents, "{} - {}\n", cmd.stat, cmd.name);
+    if !cmd.is_owner(msg.author.id.0) && !has_permission(msg) {
+        return Err(error::reply(msg, Error::PermissionDenied(strings::Error::CommandTriggerNoPermission)));
     }
 
-    let _ = write!(contents, "```");
+    let guild = msg.guild_id().map(|guild_id| guild_id.0);
 
-    let _ = msg.channel_id.say(&contents);
-});
+    if !trigger::can_change(&cmd, guild) {
+        let _ = msg.channel_id.say(helpers::get_error(strings::Error::TriggerOtherGuild));
+        return Ok(());
+    }
 
-command!(add(ctx, msg, args) {
-    let home_guild_id = env::var("HOME_GUILD_ID")
-        .unwrap_or("0".to_string()).parse::<u64>().unwrap();
+    // prefix commands only match their name
+    if kind == TriggerKind::Prefix {
+        try_reply!(msg, db.set_trigger(&name, kind, None, None));
+        trigger::get(ctx).invalidate();
 
-    // limit command adding to the main guild
-    if let Some(guild_id) = msg.guild_id() {
-        if guild_id.0 != home_guild_id || home_guild_id == 0 {
-            let _ = msg.channel_id.say(helpers::get_error("home_guild"));
-            return Ok(());
-        }
-    } else { // return if no guild found (maybe in dms?)
-        let _ = msg.channel_id.say(helpers::get_error("home_guild"));
-        return Ok(()); 
+        let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::TriggerRemoved, &[("name", &name)]));
+        return Ok(());
     }
 
-    let name = match args.single::<String>() {
-        Ok(val) => val,
-        Err(why) => {
-            let _ = msg.channel_id.say(&format!("Error: {}", why));
-            return Ok(());
-        },
-    };
+    // other triggers respond to anyone's messages, so they're limited to members
+    // that can manage the guild they respond in
+    if !has_permission(msg) {
+        let _ = msg.channel_id.say(helpers::get_error(strings::Error::TriggerNoPermission));
+        return Ok(());
+    }
 
-    let url = match args.single::<String>() {
-        Ok(val) => val,
-        Err(why) => {
-            let _ = msg.channel_id.say(&format!("Error: {}", why));
-            return Ok(());
-        },
-    };
+    let pattern = try_reply!(msg, trigger::validate(kind, &args.full()));
 
-    let mut data = ctx.data.lock();
-    let db = data.get_mut::<sqlite::Database>().unwrap();
+    try_reply!(msg, db.set_trigger(&name, kind, Some(&pattern), guild));
+    trigger::get(ctx).invalidate();
+
+    let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::TriggerUpdated, &[
+        ("name", &name),
+        ("kind", &kind.as_str()),
+        ("pattern", &pattern),
+    ]));
+});
+
+command!(channel_triggers(ctx, msg, args) {
+    let value = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+    let enabled = try_reply!(msg, parse_switch(&value));
+
+    if !has_permission(msg) {
+        let _ = msg.channel_id.say(helpers::get_error(strings::Error::TriggersNoPermission));
+        return Ok(());
+    }
 
-    if !try!(db.is_command(&name)) {
-        try!(db.add(&name, &url, msg.author.id.0));
-        let _ = msg.channel_id.say(helpers::get_info_f("command_added", &[&name, &url]));
+    try_reply!(msg, store::get(ctx).set_channel_triggers(msg.channel_id.0, enabled));
+    trigger::get(ctx).invalidate();
+
+    if enabled {
+        let _ = msg.channel_id.say(helpers::get_info(strings::Info::TriggersEnabled));
     } else {
-        let _ = msg.channel_id.say(helpers::get_error_f("command_exists", &[&name]));
+        let _ = msg.channel_id.say(helpers::get_info(strings::Info::TriggersDisabled));
     }
 });
 
+// parses `on` or `off`
+fn parse_switch(value: &str) -> Result<bool, Error> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        other => Err(Error::invalid_input(helpers::get_error_f(strings::Error::InvalidSwitch,
+                                                               &[("value", &other)]))),
+    }
+}
 
-command!(delete(ctx, msg, args) {
-    let name = match args.single::<String>() {
-        Ok(val) => val,
-        Err(why) => {
-            let _ = msg.channel_id.say(&format!("Error: {}", why));
-            return Ok(());
-        },
-    };
+command!(action(ctx, msg, args) {
+    let name = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+    let kind = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
 
-    let mut data = ctx.data.lock();
-    let db = data.get_mut::<sqlite::Database>().unwrap();
+    let db = store::get(ctx);
 
-    if try!(db.is_command(&name)) {
-        let cmd = try!(db.get(&name));
+    let cmd = try_reply!(msg, db.get(&name));
 
-        if cmd.is_owner(msg.author.id.0) || has_permission(msg) {
-            try!(db.delete(&name));
-            let _ = msg.channel_id.say(helpers::get_info_f("command_deleted", &[&name]));
-        }
-    } else {
-        let _ = msg.channel_id.say(helpers::get_error_f("command_not_found", &[&name]));
-    }  
-});
+    if !cmd.is_owner(msg.author.id.0) && !has_permission(msg) {
+        return Err(error::reply(msg, Error::PermissionDenied(strings::Error::CommandActionNoPermission)));
+    }
 
-command!(edit(ctx, msg, args) {
-    let name = match args.single::<String>() {
-        Ok(val) => val,
-        Err(why) => {
-            let _ = msg.channel_id.say(&format!("Error: {}", why));
-            return Ok(());
-        },
-    };
+    let mut actions = cmd.actions.clone();
 
-    let new_name = match args.single::<String>() {
-        Ok(val) => val,
-        Err(why) => {
-            let _ = msg.channel_id.say(&format!("Error: {}", why));
-            return Ok(());
+    match kind.to_lowercase().as_str() {
+        "react" => {
+            let reactions: Vec<String> = args.full().split_whitespace().map(|emoji| emoji.to_string()).collect();
+
+            if reactions.len() > actions::MAX_REACTIONS {
+                return Err(error::reply(msg, Error::invalid_input(
+                    helpers::get_error_f(strings::Error::ActionTooManyReactions,
+                                         &[("max", &actions::MAX_REACTIONS)]))));
+            }
+
+            actions.reactions = reactions;
         },
-    };
+        "delete" => {
+            let value = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+            actions.delete_trigger = try_reply!(msg, parse_switch(&value));
 
-    let new_url = match args.single::<String>() {
-        Ok(val) => val,
-        Err(why) => {
-            let _ = msg.channel_id.say(&format!("Error: {}", why));
-            return Ok(());
+            // deleting other members' messages needs the same permission as moderating them
+            if actions.delete_trigger && !has_permission(msg) {
+                let _ = msg.channel_id.say(helpers::get_error(strings::Error::ActionDeleteNoPermission));
+                return Ok(());
+            }
         },
-    };
+        "dm" => {
+            let value = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+            actions.dm = try_reply!(msg, parse_switch(&value));
+        },
+        "silent" => {
+            let value = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+            actions.silent = try_reply!(msg, parse_switch(&value));
+        },
+        "channel" => {
+            // no channel sends the response where the command was used again
+            actions.channel = if args.full().is_empty() {
+                None
+            } else {
+                let channel = try_reply!(msg, args.single::<ChannelId>().map_err(Error::invalid_input));
+                Some(channel.0)
+            };
+        },
+        "clear" => actions = Default::default(),
+        other => {
+            return Err(error::reply(msg, Error::invalid_input(
+                helpers::get_error_f(strings::Error::ActionUnknown, &[("action", &other)]))));
+        },
+    }
 
-    let mut data = ctx.data.lock();
-    let db = data.get_mut::<sqlite::Database>().unwrap();
+    try_reply!(msg, db.set_actions(&name, &actions));
+    let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::ActionsUpdated, &[("name", &name)]));
+});
 
-    // check if command exists
-    if try!(db.is_command(&name)) {
-        let cmd = try!(db.get(&name));
+command!(set_script(ctx, msg, args) {
+    let name = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+    let mut raw_json = args.full();
 
-        // check permissions
-        if cmd.is_owner(msg.author.id.0) || has_permission(msg) {
-            // check if new name conflicts
-            if try!(db.is_command(&new_name)) {
-                let _ = msg.channel_id.say(helpers::get_error_f("command_exists", &[&new_name]));
-            } else {
-                try!(db.edit(&name, &new_name, &new_url));
-                let _ = msg.channel_id.say(helpers::get_info_f("command_updated", &[&name, &new_name, &new_url]));
-            }
-        } else { // no permissions
-            let _ = msg.channel_id.say(helpers::get_error("command_edit_no_permission"));
-        }
+    // try reading file
+    if raw_json.is_empty() && msg.attachments.len() > 0 {
+        let json_bytes = try_reply!(msg, msg.attachments[0].download());
+        raw_json = try_reply!(msg, String::from_utf8(json_bytes).map_err(Error::invalid_input));
+    }
+
+    let db = store::get(ctx);
+
+    let cmd = try_reply!(msg, db.get(&name));
+
+    if !cmd.is_owner(msg.author.id.0) && !has_permission(msg) {
+        return Err(error::reply(msg, Error::PermissionDenied(strings::Error::CommandScriptNoPermission)));
+    }
+
+    if raw_json.trim() == "clear" {
+        try_reply!(msg, db.set_script(&name, None));
+        let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::ScriptRemoved, &[("name", &name)]));
+        return Ok(());
+    }
+
+    // allow pasting the script in a code block
+    let raw_json = raw_json.trim().trim_matches('`').trim_left_matches("json");
+
+    let steps: Vec<Step> = try_reply!(msg, serde_json::from_str(raw_json));
+    try_reply!(msg, script::validate(&steps));
+
+    try_reply!(msg, db.set_script(&name, Some(&steps)));
+    let _ = msg.channel_id.say(helpers::get_info_n(strings::Info::ScriptUpdated, steps.len() as u64,
+                                                   &[("name", &name)]));
+});
+
+command!(cancel(ctx, msg, _args) {
+    // members that can manage commands can cancel anyone's scripts
+    let user = if has_permission(msg) { None } else { Some(msg.author.id) };
+
+    let cancelled = script::get(ctx).cancel(msg.channel_id, user);
+
+    if cancelled == 0 {
+        let _ = msg.channel_id.say(helpers::get_error(strings::Error::ScriptsNotRunning));
     } else {
-        let _ = msg.channel_id.say(helpers::get_error_f("command_not_found", &[&name]));
-    }  
+        let _ = msg.channel_id.say(helpers::get_info_n(strings::Info::ScriptsCancelled, cancelled as u64, &[]));
+    }
 });
 
+fn format_timestamp(timestamp: i64) -> String {
+    Utc.timestamp(timestamp, 0).format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+// checks if a response links directly to an image that can be previewed
+fn is_image(url: &str) -> bool {
+    let path = url.split(|c| c == '?' || c == '#').next().unwrap_or("").to_lowercase();
+
+    (path.starts_with("http://") || path.starts_with("https://")) &&
+        IMAGE_EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
 command!(stat(ctx, msg, args) {
-    let name = match args.single::<String>() {
-        Ok(val) => val,
-        Err(why) => {
-            let _ = msg.channel_id.say(&format!("Error: {}", why));
-            return Ok(());
-        },
-    };
+    let name = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
 
-    let mut data = ctx.data.lock();
-    let db = data.get_mut::<sqlite::Database>().unwrap();
+    let db = store::get(ctx);
 
-    let cmd = match db.get(&name) {
-        Ok(val) => val,
-        Err(e) => {
-            let _ = msg.channel_id.say(&format!("Error: {}", e));
-            return Ok(());
-        }
-    };
+    let cmd = try_reply!(msg, db.get(&name));
+    let rank = try_reply!(msg, db.rank(&cmd));
 
-    let timestamp = Utc.timestamp(cmd.created as i64, 0).format("%Y-%m-%d %H:%M:%S").to_string();
+    let owner = helpers::user_tag(cmd.owner);
+    let created = format_timestamp(cmd.created as i64);
+    let last_used = cmd.last_used.map_or_else(|| helpers::get_info(strings::Info::StatNeverUsed), format_timestamp);
+    let trigger = match cmd.pattern {
+        Some(ref pattern) if cmd.trigger != TriggerKind::Prefix => Some(format!("{} `{}`", cmd.trigger.as_str(), pattern)),
+        _ => None,
+    };
+    let edited = match (cmd.edited_by, cmd.edited) {
+        (Some(editor), Some(edited)) => Some(helpers::get_info_f(strings::Info::StatEditedValue, &[
+            ("user", &helpers::user_tag(editor)),
+            ("date", &format_timestamp(edited)),
+        ])),
+        _ => None,
+    };
 
     let _ = msg.channel_id.send_message(|m| m
-        .embed(|e| e
-            .title(format!("Stats for {}", name))
-            .field(|f| f
-                .name("Response")
-                .value(&cmd.url)
-                .inline(false)
-            )
-            .field(|f| f
-                .name("Times used")
-                .value(&cmd.stat)
-            )
-            .field(|f| f
-                .name("Added on")
-                .value(timestamp)
-            )
-            .field(|f| f
-                .name("Added by")
-                .value(format!("<@{}>", &cmd.owner)
-            ))));
+        .embed(|e| {
+            let mut e = e
+                .title(helpers::get_info_f(strings::Info::StatTitle, &[("name", &name)]))
+                .field(|f| f
+                    .name(&helpers::get_info(strings::Info::StatResponse))
+                    .value(chunk::truncate(&cmd.url, chunk::EMBED_FIELD_LIMIT))
+                    .inline(false)
+                )
+                .field(|f| f
+                    .name(&helpers::get_info(strings::Info::StatUses))
+                    .value(&cmd.stat)
+                )
+                .field(|f| f
+                    .name(&helpers::get_info(strings::Info::StatRank))
+                    .value(format!("#{}", rank))
+                )
+                .field(|f| f
+                    .name(&helpers::get_info(strings::Info::StatLastUsed))
+                    .value(&last_used)
+                )
+                .field(|f| f
+                    .name(&helpers::get_info(strings::Info::StatCreated))
+                    .value(&created)
+                )
+                .field(|f| f
+                    .name(&helpers::get_info(strings::Info::StatOwner))
+                    .value(&owner)
+                );
+
+            if let Some(ref trigger) = trigger {
+                e = e.field(|f| f
+                    .name(&helpers::get_info(strings::Info::StatTrigger))
+                    .value(trigger)
+                );
+            }
+
+            if let Some(ref edited) = edited {
+                e = e.field(|f| f
+                    .name(&helpers::get_info(strings::Info::StatEdited))
+                    .value(edited)
+                );
+            }
+
+            if is_image(&cmd.url) {
+                e = e.image(&cmd.url);
+            }
+
+            e
+        }));
 });
 
 command!(search(ctx, msg, args) {
-    let search = match args.single::<String>() {
-        Ok(val) => val,
-        Err(why) => {
-            let _ = msg.channel_id.say(&format!("Error: {}", why));
-            return Ok(());
-        },
-    };
-
-    let mut data = ctx.data.lock();
-    let db = data.get_mut::<sqlite::Database>().unwrap();
+    let search = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
 
-    let results = try!(db.search(&search));
+    let results = try_reply!(msg, store::get(ctx).search(&search));
 
     let mut contents = String::new();
 
     if results.len() == 0 {
-        let _ = msg.channel_id.say(helpers::get_error("search_no_results"));
+        let _ = msg.channel_id.say(helpers::get_error(strings::Error::SearchNoResults));
         return Ok(());
     }
 
@@ -320,31 +450,8 @@ command!(search(ctx, msg, args) {
             let _ = write!(contents, "{}\n", name=cmd.name);
     }
 
-    let messages = split_message(&contents, Some("Search Results:"), true);
-
-    // only 1 short message
-    if messages.len() == 1 && messages[0].len() <= 300 {
-        let _ = msg.channel_id.say(&messages[0]);
-        return Ok(());
-    }
-
-    let dm = match msg.author.create_dm_channel() {
-        Ok(val) => val,
-        Err(_) => {
-            let _ = msg.channel_id.say("Failed to send DM, maybe you don't have them enabled?");
-            return Ok(());
-        }
-    };
-
-    // send results
-    for msg in messages {
-        let _ = dm.say(&msg);
-    }
-
-    // send notification in channel
-    if !msg.is_private() {
-        let _ = msg.channel_id.say(":mailbox_with_mail: High number of search results, sent you a DM.");
-    }
+    let pages = chunk::chunk_code_block(contents.trim_right(), "", PAGE_LENGTH);
+    pagination::send(ctx, msg, &helpers::get_info(strings::Info::SearchTitle), pages);
 });
 
 
@@ -358,61 +465,55 @@ command!(import(ctx, msg, args) {
 
     // try reading file
     if raw_json.is_empty() && msg.attachments.len() > 0 {
-        let json_bytes = match msg.attachments[0].download() {
-            Ok(content) => content,
-            Err(why) => {
-                // println!(helpers::get_error_f("download_attachment", &[why]).to_string());
-                let _ = msg.channel_id.say(helpers::get_error_f("download_attachment", &[&why.to_string()]));
-
-                return Ok(());
-            },
-        };
+        let json_bytes = try_reply!(msg, msg.attachments[0].download());
+        raw_json = try_reply!(msg, String::from_utf8(json_bytes).map_err(Error::invalid_input));
+    }
 
-        raw_json = match String::from_utf8(json_bytes) {
-            Ok(content) => content,
-            Err(why) => {
-                let _ = msg.channel_id.say(helpers::get_error_f("utf8", &[&why.to_string()]));
+    let imported: Command = try_reply!(msg, serde_json::from_str(&raw_json));
 
-                return Ok(());
-            }
-        };
-    }
+    // validate every entry before anything is replaced
+    let mut entries = Vec::new();
+    let mut reserved = Vec::new();
 
-    let imported: Command = match serde_json::from_str(&raw_json) {
-        Ok(val) => val,
-        Err(why) => {
-            let _ = msg.channel_id.say(helpers::get_error_f("json", &[&why.to_string()]));
-            return Ok(());
+    for (key, value) in imported.commands.iter() {
+        if is_reserved(key) {
+            reserved.push(key.clone());
+            continue;
         }
-    };
 
-    let mut data = ctx.data.lock();
-    let db = data.get_mut::<sqlite::Database>().unwrap();
+        let url = try_reply!(msg, value.as_str().ok_or_else(|| {
+            Error::invalid_input(helpers::get_error_f(strings::Error::ImportInvalidResponse, &[("name", key)]))
+        }));
+
+        entries.push((key, url));
+    }
+
+    let db = store::get(ctx);
 
     let _ = msg.react("👌");
 
-    let _ = msg.channel_id.say(helpers::get_info_f("import_started", &[&imported.commands.len().to_string()]));
+    let _ = msg.channel_id.say(helpers::get_info_n(strings::Info::ImportStarted, entries.len() as u64, &[]));
     let mut existing = 0;
 
-    match db.delete_all() {
-        Ok(()) => {},
-        Err(why) => {
-            let _ = msg.channel_id.say(helpers::get_error_f("import_delete_all", &[&why.to_string()]));
-            return Ok(());
-        }
-    };
+    try_reply!(msg, db.delete_all());
+    trigger::get(ctx).invalidate();
 
-    for (key, value) in imported.commands.iter() {
-        if !try!(db.is_command(&key)) {
-            try!(db.add(&key, &value.as_str().unwrap().to_string(), msg.author.id.0));
-        } else {
-            existing += 1;
+    for (key, url) in entries {
+        match db.add(key, url, msg.author.id.0) {
+            Ok(()) => {},
+            Err(Error::AlreadyExists(_)) => existing += 1,
+            Err(why) => return Err(error::reply(msg, why)),
         }
     }
 
+    if !reserved.is_empty() {
+        let _ = msg.channel_id.say(helpers::get_error_f(strings::Error::ImportReserved,
+                                                        &[("names", &reserved.join(", "))]));
+    }
+
     if existing > 0 {
-        let _ = msg.channel_id.say(helpers::get_error_f("import_existing", &[&existing.to_string()]));
+        let _ = msg.channel_id.say(helpers::get_error_n(strings::Error::ImportExisting, existing, &[]));
     } else {
-        let _ = msg.channel_id.say(helpers::get_info("import_finished"));
+        let _ = msg.channel_id.say(helpers::get_info(strings::Info::ImportFinished));
     }
 });
\ No newline at end of file
@@ -0,0 +1,104 @@
+use store;
+use std::collections::HashSet;
+use std::fmt::Write;
+use serenity::model::{Message, UserId};
+use helpers;
+use strings;
+use error::Error;
+
+// ids of the members of the guild a message was sent in, None outside of guilds
+fn guild_members(msg: &Message) -> Option<HashSet<u64>> {
+    let guild = match msg.guild() {
+        Some(guild) => guild,
+        None => return None,
+    };
+    let guild = guild.read().unwrap();
+
+    Some(guild.members.keys().map(|id| id.0).collect())
+}
+
+// creators limited to the members of the current guild
+fn scoped_creators(msg: &Message, creators: Vec<store::Creator>) -> Vec<store::Creator> {
+    match guild_members(msg) {
+        Some(members) => creators
+            .into_iter()
+            .filter(|c| members.contains(&(c.owner as u64)))
+            .collect(),
+        None => creators,
+    }
+}
+
+command!(creators(ctx, msg, _args) {
+    let creators = try_reply!(msg, store::get(ctx).creators());
+
+    let creators = scoped_creators(msg, creators);
+
+    let mut contents = format!("```{}\n", helpers::get_info(strings::Info::CreatorsTitle));
+
+    for creator in creators.iter().take(10) {
+        let _ = write!(contents, "{}\n", helpers::get_info_f(strings::Info::CreatorsEntry, &[
+            ("commands", &helpers::get_info_n(strings::Info::CommandsCount, creator.commands as u64, &[])),
+            ("uses", &helpers::get_info_n(strings::Info::UsesCount, creator.uses as u64, &[])),
+            ("user", &helpers::user_tag(creator.owner)),
+        ]));
+    }
+
+    let _ = write!(contents, "```");
+
+    let _ = msg.channel_id.say(&contents);
+});
+
+command!(profile(ctx, msg, args) {
+    let user_id = if args.full().is_empty() {
+        msg.author.id
+    } else {
+        try_reply!(msg, args.single::<UserId>().map_err(|_| {
+            Error::invalid_input(helpers::get_error(strings::Error::ProfileInvalidUser))
+        }))
+    };
+
+    let db = store::get(ctx);
+
+    let commands = try_reply!(msg, db.by_owner(user_id.0));
+    let creators = try_reply!(msg, db.creators());
+
+    let tag = helpers::user_tag(user_id.0 as i64);
+
+    if commands.is_empty() {
+        let _ = msg.channel_id.say(helpers::get_error_f(strings::Error::ProfileNoCommands, &[("user", &tag)]));
+        return Ok(());
+    }
+
+    let uses: u32 = commands.iter().map(|cmd| cmd.stat).sum();
+
+    let rank = scoped_creators(msg, creators)
+        .iter()
+        .position(|c| c.owner as u64 == user_id.0)
+        .map_or_else(|| helpers::get_info(strings::Info::NotAvailable), |pos| format!("#{}", pos + 1));
+
+    let mut top = String::new();
+    for cmd in commands.iter().take(5) {
+        let _ = write!(top, "{} - {}\n", cmd.stat, cmd.name);
+    }
+
+    let _ = msg.channel_id.send_message(|m| m
+        .embed(|e| e
+            .title(helpers::get_info_f(strings::Info::ProfileTitle, &[("user", &tag)]))
+            .field(|f| f
+                .name(&helpers::get_info(strings::Info::ProfileCommands))
+                .value(commands.len())
+            )
+            .field(|f| f
+                .name(&helpers::get_info(strings::Info::ProfileUses))
+                .value(uses)
+            )
+            .field(|f| f
+                .name(&helpers::get_info(strings::Info::ProfileRank))
+                .value(&rank)
+            )
+            .field(|f| f
+                .name(&helpers::get_info(strings::Info::ProfileTop))
+                .value(format!("```{}```", top))
+                .inline(false)
+            )));
+});
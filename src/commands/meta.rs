@@ -1,7 +1,31 @@
+use backup;
+use config;
+use error::Error;
+use helpers;
+use strings;
+use store;
+use trigger;
+use locale;
+use commands::custom_commands::has_permission;
+
+command!(ping(_ctx, msg) {
+    let _ = msg.channel_id.say(helpers::get_info(strings::Info::Pong));
+});
+
+command!(info(ctx, msg) {
+    let config = config::get(ctx);
+
+    let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::BotInfo, &[
+        ("owner", &config.bot.owner_name),
+        ("invite", &config.bot.invite_link),
+        ("guild", &config.home_guild.name),
+    ]));
+});
+
 command!(latency(ctx, msg) {
     let latency = ctx.shard.lock()
         .latency()
-        .map_or_else(|| "N/A".to_string(), |s| {
+        .map_or_else(|| helpers::get_info(strings::Info::NotAvailable), |s| {
             format!("{}.{}s", s.as_secs(), s.subsec_nanos())
         });
 
@@ -11,10 +35,106 @@ command!(latency(ctx, msg) {
 command!(shutdown(ctx, msg) {
     match ctx.quit() {
         Ok(()) => {
-            let _ = msg.reply("Shutting down. :wave:");
+            let _ = msg.reply(&helpers::get_info(strings::Info::ShuttingDown));
         },
         Err(why) => {
-            let _ = msg.reply(&format!("Failed to shutdown: {:?}", why));
+            let why = format!("{:?}", why);
+            let _ = msg.reply(&helpers::get_error_f(strings::Error::ShutdownFailed, &[("error", &why)]));
         }
     }
 });
+
+command!(backup(ctx, msg, _args) {
+    let db = store::get(ctx);
+    let config = config::get(ctx);
+
+    let name = try_reply!(msg, backup::create(&*db, &config.backup));
+    let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::BackupCreated, &[("name", &name)]));
+});
+
+command!(restore(ctx, msg, args) {
+    let config = config::get(ctx);
+
+    // list the available backups if none is given
+    if args.full().is_empty() {
+        let backups = try_reply!(msg, backup::list(&config.backup));
+
+        if backups.is_empty() {
+            let _ = msg.channel_id.say(helpers::get_error(strings::Error::BackupsEmpty));
+        } else {
+            let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::BackupsList,
+                                                           &[("backups", &backups.join("\n"))]));
+        }
+
+        return Ok(());
+    }
+
+    let name = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input));
+    let db = store::get(ctx);
+
+    let _ = msg.react("👌");
+
+    try_reply!(msg, backup::restore(&*db, &config.backup, &name));
+    trigger::get(ctx).invalidate();
+    let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::RestoreFinished, &[("name", &name)]));
+});
+
+command!(language(ctx, msg, args) {
+    let languages = locale::languages().join(", ");
+
+    let guild_id = match msg.guild_id() {
+        Some(guild_id) => guild_id,
+        None => {
+            let _ = msg.channel_id.say(helpers::get_error(strings::Error::LanguageGuildOnly));
+            return Ok(());
+        },
+    };
+
+    // show the current language if none is given
+    if args.full().is_empty() {
+        let current = try_reply!(msg, locale::get_languages(ctx).guild(&*store::get(ctx), guild_id.0));
+        let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::LanguageCurrent,
+                                                       &[("language", &current), ("languages", &languages)]));
+
+        return Ok(());
+    }
+
+    let language = try_reply!(msg, args.single::<String>().map_err(Error::invalid_input)).to_lowercase();
+
+    if !has_permission(msg) {
+        let _ = msg.channel_id.say(helpers::get_error(strings::Error::LanguageNoPermission));
+        return Ok(());
+    }
+
+    if !locale::exists(&language) {
+        let _ = msg.channel_id.say(helpers::get_error_f(strings::Error::LanguageUnknown,
+                                                        &[("language", &language), ("languages", &languages)]));
+        return Ok(());
+    }
+
+    // guilds using the default language aren't stored
+    let stored = if language == locale::DEFAULT_LANGUAGE {
+        None
+    } else {
+        Some(language.as_str())
+    };
+
+    try_reply!(msg, store::get(ctx).set_guild_language(guild_id.0, stored));
+    locale::get_languages(ctx).invalidate();
+
+    // reply in the new language
+    locale::set_language(&language);
+    let _ = msg.channel_id.say(helpers::get_info_f(strings::Info::LanguageUpdated, &[("language", &language)]));
+});
+
+command!(reload_locale(_ctx, msg) {
+    let problems = try_reply!(msg, locale::reload());
+    let count = locale::languages().len() as u64;
+
+    if problems.is_empty() {
+        let _ = msg.channel_id.say(helpers::get_info_n(strings::Info::LocaleReloaded, count, &[]));
+    } else {
+        let _ = msg.channel_id.say(helpers::get_info_n(strings::Info::LocaleReloadedProblems, count,
+                                                       &[("problems", &problems.join("\n"))]));
+    }
+});
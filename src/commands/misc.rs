@@ -1,53 +1,554 @@
-use reqwest;
-use reqwest::header::ContentType;
+use std::fmt::Write;
+use serenity::model::{Message, MessageId};
+use serenity::prelude::Context;
+use playground;
+use playground::{Options, Output, Target};
+use helpers;
+use strings;
+use chunk;
+use error::Result;
+use config;
+use rerun;
 
-#[derive(Deserialize)]
-struct Response {
-    stderr: String,
-    stdout: String,
+/// The code of a playground command, or what to reply with instead
+enum Input {
+    Code(Options, String),
+    /// The help, or how to use the command if there's no code
+    Notice(String),
 }
 
-command!(play(_ctx, msg, args) {
-    let mut code = args.full();
+// parses the flags and code block of a playground command
+fn read_input(ctx: &Context, msg: &Message, input: &str) -> Result<Input> {
+    let config = config::get(ctx);
+    let prefix = &config.discord.prefix;
+
+    // flags come before the code block
+    let (flags, block) = input.split_at(input.find("```").unwrap_or(input.len()));
+    let options = try!(playground::parse_options(flags, prefix));
+
+    if options.help {
+        return Ok(Input::Notice(helpers::get_info_f(strings::Info::PlayHelp, &[("prefix", prefix)])));
+    }
 
     // check if using code block
-    if !code.starts_with("```") || !code.ends_with("```") {
-        let _ = msg.channel_id.say("Missing code block");
-        let _ = msg.react("❌");
-        return Ok(());
+    match playground::extract_code(block) {
+        Some(code) => {
+            let _ = msg.react("👌");
+            Ok(Input::Code(options, code.to_string()))
+        },
+        None => {
+            let _ = msg.react("❌");
+            Ok(Input::Notice(helpers::get_error_f(strings::Error::PlayMissingCodeBlock, &[("prefix", prefix)])))
+        },
+    }
+}
+
+// parses the flags and code block of a playground command, replying with the
+// help or how to use it instead if there's no code
+fn parse_input(ctx: &Context, msg: &Message, input: &str) -> Result<Option<(Options, String)>> {
+    match try!(read_input(ctx, msg, input)) {
+        Input::Code(options, code) => Ok(Some((options, code))),
+        Input::Notice(notice) => {
+            let _ = msg.channel_id.say(notice);
+            Ok(None)
+        },
+    }
+}
+
+// room left in a message for the status and code fences around previews
+const PREVIEW_LIMIT: usize = chunk::MESSAGE_LIMIT - 200;
+
+const OUTPUT_FILE: &'static str = "output.txt";
+
+/// Output shown in its own code block
+#[derive(Clone, Copy)]
+struct Block<'a> {
+    name: &'a str,
+    lang: &'a str,
+    text: &'a str,
+}
+
+// keeps output from mentioning anyone or closing the code block it's in, by
+// putting zero width spaces after @ and between consecutive backticks
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut previous = None;
+
+    for c in text.chars() {
+        if c == '`' && previous == Some('`') {
+            escaped.push('\u{200B}');
+        }
+
+        escaped.push(c);
+
+        if c == '@' {
+            escaped.push('\u{200B}');
+        }
+
+        previous = Some(c);
     }
 
-    let _ = msg.react("👌");
+    escaped
+}
 
-    // clean up input
-    code = code.replace("```rust", "");
-    code = code.replacen("```", "", 2); // 2 in case rust in top of code block isn't used
-    code = code.replace("\"", "\\\"");  // escape quotes
-    code = code.replace("\n", "\\n");   // escape new lines
+fn format_blocks(status: &str, blocks: &[Block], limit: Option<usize>) -> String {
+    let mut contents = status.to_string();
 
-    // create json data
-    let mut data = r#"{"channel":"stable","mode":"debug","crateType":"bin","tests":false,"code": "{CODE}"}"#.to_string();
-    data = data.replace("{CODE}", &code);
+    for block in blocks {
+        let text = escape(block.text.trim_right());
+        let text = match limit {
+            Some(limit) => chunk::truncate(&text, limit),
+            None => text,
+        };
 
-    // send data
-    let client = reqwest::Client::new();
-    let res = client.post("http://play.integer32.com/execute")
-        .body(data)
-        .header(ContentType::json())
-        .send()?.error_for_status();
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
 
-    // check response
-    match res {
-        Ok(mut val) => {
-            let res_obj: Response = val.json()?;
+        let _ = write!(contents, "```{}\n{}\n```", block.lang, text);
+    }
 
-            let mut clean = res_obj.stdout.replace("@", "@\u{200B}");
-            clean = clean.replace("`", "'");
+    contents
+}
 
-            let _ = msg.channel_id.say(format!("```rust\n{}\n{}\n```", res_obj.stderr, clean));
+/// Sends a message with an optional file, or replaces `reply` with it if given,
+/// returning the id of the message.  Replies are only edited when neither the
+/// old nor the new message has a file, otherwise the old reply is deleted and a
+/// new one sent, as files can't be added or removed by editing.
+pub fn send_reply(msg: &Message, reply: Option<MessageId>, contents: &str, file: Option<&str>) -> Option<MessageId> {
+    if let Some(reply) = reply {
+        let editable = file.is_none() && match msg.channel_id.message(reply) {
+            Ok(old) => old.attachments.is_empty(),
+            Err(_) => false,
+        };
+
+        if editable {
+            match msg.channel_id.edit_message(reply, |m| m.content(contents)) {
+                Ok(_) => return Some(reply),
+                Err(why) => warn!("Failed to edit playground output, sending it again: {:?}", why),
+            }
+        }
+    }
+
+    let sent = match file {
+        Some(file) => match msg.channel_id.send_files(vec![(file.as_bytes(), OUTPUT_FILE)], |m| m.content(contents)) {
+            Ok(sent) => Ok(sent),
+            Err(why) => {
+                // send the preview alone if the bot can't attach files
+                warn!("Failed to attach playground output: {:?}", why);
+                msg.channel_id.say(contents)
+            },
         },
-        Err(e) => {
-            let _ = msg.channel_id.say(format!("Error: {}", e));
+        None => msg.channel_id.say(contents),
+    };
+
+    let sent = match sent {
+        Ok(sent) => sent.id,
+        Err(why) => {
+            warn!("Failed to send playground output: {:?}", why);
+            return None;
+        },
+    };
+
+    if let Some(reply) = reply {
+        if let Err(why) = msg.channel_id.delete_message(reply) {
+            warn!("Failed to delete old playground output {}: {:?}", reply, why);
         }
     }
+
+    Some(sent)
+}
+
+/// What `send` posts for some output
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Message(String),
+    /// A preview in the message with the whole output in a file
+    Attachment { preview: String, file: String },
+}
+
+// lays out the status and output, attaching the whole output as a file with a
+// preview in the message if it doesn't fit in one
+fn prepare(status: &str, blocks: &[Block]) -> Reply {
+    let blocks: Vec<Block> = blocks.iter().filter(|block| !block.text.trim().is_empty()).cloned().collect();
+
+    if blocks.is_empty() {
+        let contents = format!("{}\n{}", status, helpers::get_info(strings::Info::PlayNoOutput));
+        return Reply::Message(contents.trim().to_string());
+    }
+
+    let contents = format_blocks(status, &blocks, None);
+
+    if contents.chars().count() <= chunk::MESSAGE_LIMIT {
+        return Reply::Message(contents);
+    }
+
+    let preview = format_blocks(status, &blocks, Some(PREVIEW_LIMIT / blocks.len()));
+
+    let mut file = String::new();
+    for block in &blocks {
+        let _ = write!(file, "--- {} ---\n{}\n\n", block.name, block.text.trim_right());
+    }
+
+    Reply::Attachment {
+        preview: preview,
+        file: file,
+    }
+}
+
+// sends the status and output, see `prepare`, replacing `reply` if given
+fn send(msg: &Message, status: &str, blocks: &[Block], reply: Option<MessageId>) -> Option<MessageId> {
+    match prepare(status, blocks) {
+        Reply::Message(contents) => send_reply(msg, reply, &contents, None),
+        Reply::Attachment { preview, file } => send_reply(msg, reply, &preview, Some(&file)),
+    }
+}
+
+// sends a single block of output without a status
+fn send_block(msg: &Message, lang: &str, text: &str) {
+    send(msg, "", &[Block { name: "output", lang: lang, text: text }], None);
+}
+
+// cargo's progress lines, only shown if compiling fails
+fn is_cargo_status(line: &str) -> bool {
+    let line = line.trim_left();
+
+    line.starts_with("Compiling playground") || line.starts_with("Finished ") ||
+        line.starts_with("Running `")
+}
+
+fn send_output(msg: &Message, output: &Output, reply: Option<MessageId>) -> Option<MessageId> {
+    let stderr = if output.success {
+        output.stderr.lines().filter(|line| !is_cargo_status(line)).collect::<Vec<_>>().join("\n")
+    } else {
+        output.stderr.clone()
+    };
+
+    let status = if output.success {
+        helpers::get_info(strings::Info::PlaySuccess)
+    } else {
+        helpers::get_error(strings::Error::PlayFailed)
+    };
+
+    send(msg, &status, &[
+        Block { name: "stderr", lang: "", text: &stderr },
+        Block { name: "stdout", lang: "", text: &output.stdout },
+    ], reply)
+}
+
+/// Runs the code of a `~play` message, replacing `reply` with the output
+/// instead of sending a new message if given, see `send_reply`.  Returns the
+/// id of the message with the output, or the help if there's no code.
+pub fn run_play(ctx: &Context, msg: &Message, input: &str, reply: Option<MessageId>) -> Result<Option<MessageId>> {
+    let (options, code) = match try!(read_input(ctx, msg, input)) {
+        Input::Code(options, code) => (options, code),
+        Input::Notice(notice) => return Ok(send_reply(msg, reply, &notice, None)),
+    };
+
+    let code = playground::wrap_code(&code, &options);
+    let output = try!(playground::get(ctx).execute(&code, &options));
+
+    Ok(send_output(msg, &output, reply))
+}
+
+command!(play(ctx, msg, args) {
+    // editing the message runs it again
+    if let Some(reply) = try_reply!(msg, run_play(ctx, msg, &args.full(), None)) {
+        rerun::track(ctx, msg, reply);
+    }
+});
+
+command!(miri(ctx, msg, args) {
+    let (options, code) = match try_reply!(msg, parse_input(ctx, msg, &args.full())) {
+        Some(input) => input,
+        None => return Ok(()),
+    };
+
+    let code = playground::wrap_code(&code, &options);
+    let output = try_reply!(msg, playground::get(ctx).miri(&code, &options));
+
+    send_output(msg, &output, None);
+});
+
+fn compile(ctx: &Context, msg: &Message, input: &str, target: Target, lang: &str) -> Result<()> {
+    let (options, code) = match try!(parse_input(ctx, msg, input)) {
+        Some(input) => input,
+        None => return Ok(()),
+    };
+
+    let code = playground::wrap_code(&code, &options);
+    let output = try!(playground::get(ctx).compile(&code, &options, target));
+
+    if output.success {
+        send_block(msg, lang, &output.code);
+    } else {
+        send_block(msg, "rust", &output.stderr);
+    }
+
+    Ok(())
+}
+
+command!(asm(ctx, msg, args) {
+    try_reply!(msg, compile(ctx, msg, &args.full(), Target::Assembly, "x86asm"));
 });
+
+command!(mir(ctx, msg, args) {
+    try_reply!(msg, compile(ctx, msg, &args.full(), Target::Mir, "rust"));
+});
+
+// line by line diff of the longest common subsequence, code in a single
+// message is short enough for the quadratic table
+fn diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // common[i][j] is the length of the lcs of old[i..] and new[j..]
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            let _ = writeln!(diff, "  {}", old[i]);
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || common[i][j + 1] >= common[i + 1][j]) {
+            let _ = writeln!(diff, "+ {}", new[j]);
+            j += 1;
+        } else {
+            let _ = writeln!(diff, "- {}", old[i]);
+            i += 1;
+        }
+    }
+
+    diff
+}
+
+command!(fmt(ctx, msg, args) {
+    let (options, code) = match try_reply!(msg, parse_input(ctx, msg, &args.full())) {
+        Some(input) => input,
+        None => return Ok(()),
+    };
+
+    let output = try_reply!(msg, playground::get(ctx).format(&code, &options));
+
+    if !output.success {
+        send_block(msg, "rust", &output.stderr);
+    } else if output.code.trim() == code.trim() {
+        let _ = msg.channel_id.say(helpers::get_info(strings::Info::FmtUnchanged));
+    } else {
+        send_block(msg, "diff", &diff(&code, &output.code));
+    }
+});
+
+/// A warning or error reported by clippy
+struct Lint {
+    level: String,
+    message: String,
+    line: Option<String>,
+    name: Option<String>,
+}
+
+// summaries printed by cargo after the lints
+fn is_summary(message: &str) -> bool {
+    message.starts_with("`playground`") || message.starts_with("could not compile") ||
+        message.starts_with("aborting due to") || message.starts_with("build failed")
+}
+
+fn parse_lints(stderr: &str) -> Vec<Lint> {
+    let mut lints: Vec<Lint> = Vec::new();
+
+    for line in stderr.lines() {
+        let trimmed = line.trim();
+
+        let level = if line.starts_with("warning") {
+            Some("warning")
+        } else if line.starts_with("error") {
+            Some("error")
+        } else {
+            None
+        };
+
+        if let Some(level) = level {
+            // `error[E0308]: message` or `warning: message`
+            let message = match line.find(": ") {
+                Some(i) => &line[i + 2..],
+                None => continue,
+            };
+
+            if !is_summary(message) {
+                lints.push(Lint {
+                    level: level.to_string(),
+                    message: message.to_string(),
+                    line: None,
+                    name: None,
+                });
+            }
+
+            continue;
+        }
+
+        let lint = match lints.last_mut() {
+            Some(lint) => lint,
+            None => continue,
+        };
+
+        // ` --> src/main.rs:2:5`
+        if trimmed.starts_with("-->") && lint.line.is_none() {
+            lint.line = trimmed.split(':').nth(1).map(|line| line.to_string());
+        }
+
+        // `= note: `#[warn(clippy::needless_return)]` on by default`
+        if let Some(start) = trimmed.find("(clippy::") {
+            if lint.name.is_none() {
+                lint.name = trimmed[start + 1..].split(')').next().map(|name| name.to_string());
+            }
+        }
+    }
+
+    lints
+}
+
+command!(clippy(ctx, msg, args) {
+    let (options, code) = match try_reply!(msg, parse_input(ctx, msg, &args.full())) {
+        Some(input) => input,
+        None => return Ok(()),
+    };
+
+    let output = try_reply!(msg, playground::get(ctx).clippy(&code, &options));
+    let lints = parse_lints(&output.stderr);
+
+    if lints.is_empty() {
+        if output.success {
+            let _ = msg.channel_id.say(helpers::get_info(strings::Info::ClippyClean));
+        } else {
+            send_block(msg, "rust", &output.stderr);
+        }
+
+        return Ok(());
+    }
+
+    let mut contents = String::new();
+    for lint in lints {
+        let _ = write!(contents, "{}", lint.level);
+
+        if let Some(line) = lint.line {
+            let _ = write!(contents, " ({})", helpers::get_info_f(strings::Info::ClippyLine, &[("line", &line)]));
+        }
+
+        let _ = write!(contents, ": {}", lint.message);
+
+        if let Some(name) = lint.name {
+            let _ = write!(contents, " [{}]", name);
+        }
+
+        contents.push('\n');
+    }
+
+    send_block(msg, "", &contents);
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(text: &str) -> Block {
+        Block { name: "stdout", lang: "", text: text }
+    }
+
+    fn message(reply: Reply) -> String {
+        match reply {
+            Reply::Message(contents) => contents,
+            other => panic!("expected a message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn mentions_are_escaped() {
+        for mention in &["@everyone", "@here", "<@1>", "<@!1>", "<@&1>"] {
+            let escaped = escape(mention);
+
+            assert!(!escaped.contains(mention), "{} isn't escaped", mention);
+            assert_eq!(escaped.replace('\u{200B}', ""), *mention);
+        }
+
+        assert_eq!(escape("a@b"), "a@\u{200B}b");
+    }
+
+    #[test]
+    fn code_fences_are_escaped() {
+        let escaped = escape("```\nfn main() {}\n```");
+
+        assert!(!escaped.contains("``"));
+        assert_eq!(escaped.replace('\u{200B}', ""), "```\nfn main() {}\n```");
+
+        // single backticks are left alone
+        assert_eq!(escape("`x` and `y`"), "`x` and `y`");
+    }
+
+    #[test]
+    fn output_stays_in_its_code_block() {
+        let contents = message(prepare("Success", &[block("```\n@everyone\n```")]));
+
+        assert!(contents.starts_with("Success\n```\n"));
+        assert!(contents.ends_with("\n```"));
+        assert_eq!(contents.matches("```").count(), 2);
+        assert!(!contents.contains("@everyone"));
+    }
+
+    #[test]
+    fn empty_blocks_are_skipped() {
+        let contents = message(prepare("Success", &[block("  \n"), block("out")]));
+        assert_eq!(contents, "Success\n```\nout\n```");
+    }
+
+    #[test]
+    fn output_up_to_the_limit_is_sent_whole() {
+        // the status, a newline and the fences around the text
+        let overhead = "Success\n```\n\n```".len();
+        let text = "a".repeat(chunk::MESSAGE_LIMIT - overhead);
+
+        let contents = message(prepare("Success", &[block(&text)]));
+        assert_eq!(contents.chars().count(), chunk::MESSAGE_LIMIT);
+        assert!(contents.contains(&text));
+    }
+
+    #[test]
+    fn longer_output_is_attached() {
+        let overhead = "Success\n```\n\n```".len();
+        let text = "a".repeat(chunk::MESSAGE_LIMIT - overhead + 1);
+
+        match prepare("Success", &[block(&text), Block { name: "stderr", lang: "", text: "warning" }]) {
+            Reply::Attachment { preview, file } => {
+                assert!(preview.chars().count() <= chunk::MESSAGE_LIMIT);
+                assert!(preview.contains('…'));
+                assert!(preview.contains("warning"));
+                assert_eq!(file, format!("--- stdout ---\n{}\n\n--- stderr ---\nwarning\n\n", text));
+            },
+            other => panic!("expected an attachment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn previews_share_the_message() {
+        let text = "é".repeat(chunk::MESSAGE_LIMIT * 2);
+
+        match prepare("Success", &[block(&text), block(&text)]) {
+            Reply::Attachment { preview, .. } => {
+                assert!(preview.chars().count() <= chunk::MESSAGE_LIMIT);
+                assert_eq!(preview.matches("```").count(), 4);
+            },
+            other => panic!("expected an attachment, got {:?}", other),
+        }
+    }
+}
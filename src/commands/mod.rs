@@ -1,3 +1,327 @@
+use serenity::framework::standard::{help_commands, Args, CommandError, CreateCommand};
+use serenity::model::Message;
+use serenity::prelude::Context;
+
 pub mod meta;
 pub mod custom_commands;
 pub mod misc;
+pub mod leaderboards;
+
+/// What runs when a built-in command is used
+pub enum Exec {
+    Command(fn(&mut Context, &Message, Args) -> Result<(), CommandError>),
+    /// The framework's help command
+    Help,
+}
+
+/// A command registered with the framework
+pub struct Builtin {
+    pub name: &'static str,
+    pub usage: Option<&'static str>,
+    /// Shown by `help` in English in every server, the framework keeps a single
+    /// description per command so it can't follow the server's language
+    pub desc: Option<&'static str>,
+    pub owners_only: bool,
+    pub exec: Exec,
+}
+
+impl Builtin {
+    /// Configures the framework's command
+    pub fn create(&self, c: CreateCommand) -> CreateCommand {
+        let c = match self.exec {
+            Exec::Command(exec) => c.exec(exec),
+            Exec::Help => c.exec_help(help_commands::with_embeds),
+        };
+
+        let c = match self.usage {
+            Some(usage) => c.usage(usage),
+            None => c,
+        };
+
+        let c = match self.desc {
+            Some(desc) => c.desc(desc),
+            None => c,
+        };
+
+        c.owners_only(self.owners_only)
+    }
+}
+
+/// Built-in commands by group, registered with the framework in `main`.  Custom
+/// commands can't use any of these names.
+pub const GROUPS: &'static [(&'static str, &'static [Builtin])] = &[
+    ("Meta", &[
+        Builtin {
+            name: "help",
+            usage: None,
+            desc: None,
+            owners_only: false,
+            exec: Exec::Help,
+        },
+        Builtin {
+            name: "ping",
+            usage: None,
+            desc: None,
+            owners_only: false,
+            exec: Exec::Command(meta::ping),
+        },
+        Builtin {
+            name: "latency",
+            usage: None,
+            desc: Some("Calculates the heartbeat latency between the shard and the gateway."),
+            owners_only: false,
+            exec: Exec::Command(meta::latency),
+        },
+        Builtin {
+            name: "info",
+            usage: None,
+            desc: Some("Gives info about the bot."),
+            owners_only: false,
+            exec: Exec::Command(meta::info),
+        },
+        Builtin {
+            name: "shutdown",
+            usage: None,
+            desc: Some("Gracefully shuts down the bot."),
+            owners_only: true,
+            exec: Exec::Command(meta::shutdown),
+        },
+        Builtin {
+            name: "backup",
+            usage: None,
+            desc: Some("Creates a backup of the database."),
+            owners_only: true,
+            exec: Exec::Command(meta::backup),
+        },
+        Builtin {
+            name: "restore",
+            usage: Some("[backup]"),
+            desc: Some("Restores the database from a backup, lists backups if none is given."),
+            owners_only: true,
+            exec: Exec::Command(meta::restore),
+        },
+        Builtin {
+            name: "language",
+            usage: Some("[language]"),
+            desc: Some("Shows or changes the language of the bot in this server.  Changing it is limited \
+                        to members with MANAGE_GUILD permissions."),
+            owners_only: false,
+            exec: Exec::Command(meta::language),
+        },
+        Builtin {
+            name: "reload-locale",
+            usage: None,
+            desc: Some("Reloads the locale files and lists missing or extra strings."),
+            owners_only: true,
+            exec: Exec::Command(meta::reload_locale),
+        },
+    ]),
+    ("Custom Commands", &[
+        Builtin {
+            name: "commands",
+            usage: None,
+            desc: Some("Lists all available commands"),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::commands),
+        },
+        Builtin {
+            name: "top",
+            usage: None,
+            desc: Some("Lists the top 10 most used commands"),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::top),
+        },
+        Builtin {
+            name: "add",
+            usage: Some("[name] [url]"),
+            desc: Some("Adds a custom command"),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::add),
+        },
+        Builtin {
+            name: "delete",
+            usage: Some("[name]"),
+            desc: Some("Deletes a custom command.  Limited to the creator of a command or members with \
+                        MANAGE_GUILD permissions."),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::delete),
+        },
+        Builtin {
+            name: "edit",
+            usage: Some("[name] [new name] [new url]"),
+            desc: Some("Edits an existing command.  Limited to the creator of a command or members with \
+                        MANAGE_GUILD permissions."),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::edit),
+        },
+        Builtin {
+            name: "stat",
+            usage: Some("[name]"),
+            desc: Some("Shows information about a custom command."),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::stat),
+        },
+        Builtin {
+            name: "search",
+            usage: Some("[name]"),
+            desc: Some("Searches for a custom command."),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::search),
+        },
+        Builtin {
+            name: "trigger",
+            usage: Some("[name] [prefix|exact|contains|starts-with|regex] [pattern]"),
+            desc: Some("Makes a custom command respond to messages matching a pattern in this server.  \
+                        Limited to members with MANAGE_GUILD permissions, the creator of a command \
+                        can set it back to prefix."),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::set_trigger),
+        },
+        Builtin {
+            name: "triggers",
+            usage: Some("[on|off]"),
+            desc: Some("Enables or disables pattern triggers in the current channel.  Limited to \
+                        members with MANAGE_GUILD permissions."),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::channel_triggers),
+        },
+        Builtin {
+            name: "action",
+            usage: Some("[name] [react|delete|dm|channel|silent|clear] [value]"),
+            desc: Some("Changes what a custom command does besides responding.  Limited to the \
+                        creator of a command or members with MANAGE_GUILD permissions, deleting \
+                        messages is limited to members with MANAGE_GUILD permissions."),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::action),
+        },
+        Builtin {
+            name: "script",
+            usage: Some("[name] [json|clear]"),
+            desc: Some("Makes a custom command send a script instead of its response.  Limited to the \
+                        creator of a command or members with MANAGE_GUILD permissions."),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::set_script),
+        },
+        Builtin {
+            name: "cancel",
+            usage: None,
+            desc: Some("Cancels your scripts running in the current channel, or all of them \
+                        for members with MANAGE_GUILD permissions."),
+            owners_only: false,
+            exec: Exec::Command(custom_commands::cancel),
+        },
+        Builtin {
+            name: "import",
+            usage: Some("[json data]"),
+            desc: Some("Imports commands from json."),
+            owners_only: true,
+            exec: Exec::Command(custom_commands::import),
+        },
+    ]),
+    ("Leaderboards", &[
+        Builtin {
+            name: "creators",
+            usage: None,
+            desc: Some("Lists the top 10 users by commands created and their total uses"),
+            owners_only: false,
+            exec: Exec::Command(leaderboards::creators),
+        },
+        Builtin {
+            name: "profile",
+            usage: Some("[@user]"),
+            desc: Some("Shows a summary of the commands created by a user."),
+            owners_only: false,
+            exec: Exec::Command(leaderboards::profile),
+        },
+    ]),
+    ("Misc", &[
+        Builtin {
+            name: "play",
+            usage: Some("[--nightly|--beta] [--release] [--edition year] [--test] [--lib] [code block]"),
+            desc: Some("Evaluates Rust code in the playground, use the `--help` flag for details."),
+            owners_only: false,
+            exec: Exec::Command(misc::play),
+        },
+        Builtin {
+            name: "clippy",
+            usage: Some("[flags] [code block]"),
+            desc: Some("Runs clippy on Rust code and lists the lints."),
+            owners_only: false,
+            exec: Exec::Command(misc::clippy),
+        },
+        Builtin {
+            name: "fmt",
+            usage: Some("[flags] [code block]"),
+            desc: Some("Formats Rust code with rustfmt and shows the changes."),
+            owners_only: false,
+            exec: Exec::Command(misc::fmt),
+        },
+        Builtin {
+            name: "miri",
+            usage: Some("[flags] [code block]"),
+            desc: Some("Runs Rust code in miri to check for undefined behaviour."),
+            owners_only: false,
+            exec: Exec::Command(misc::miri),
+        },
+        Builtin {
+            name: "asm",
+            usage: Some("[flags] [code block]"),
+            desc: Some("Shows the assembly generated for Rust code."),
+            owners_only: false,
+            exec: Exec::Command(misc::asm),
+        },
+        Builtin {
+            name: "mir",
+            usage: Some("[flags] [code block]"),
+            desc: Some("Shows the MIR generated for Rust code."),
+            owners_only: false,
+            exec: Exec::Command(misc::mir),
+        },
+    ]),
+];
+
+/// Checks if a name is taken by a built-in command, ignoring case
+pub fn is_reserved(name: &str) -> bool {
+    let name = name.to_lowercase();
+
+    GROUPS.iter()
+        .flat_map(|&(_, commands)| commands.iter())
+        .any(|builtin| builtin.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    #[test]
+    fn builtin_names_are_unique() {
+        let mut names = HashSet::new();
+
+        for &(_, commands) in GROUPS {
+            for builtin in commands {
+                assert!(names.insert(builtin.name), "{} is registered twice", builtin.name);
+            }
+        }
+    }
+
+    #[test]
+    fn builtin_names_collide_with_custom_commands() {
+        for &(_, commands) in GROUPS {
+            for builtin in commands {
+                assert!(is_reserved(builtin.name));
+                assert!(is_reserved(&builtin.name.to_uppercase()));
+            }
+        }
+
+        assert!(is_reserved("Help"));
+        assert!(is_reserved("reload-LOCALE"));
+    }
+
+    #[test]
+    fn other_names_are_free() {
+        for name in &["hello", "helper", "pin", "add-ons", "playground", ""] {
+            assert!(!is_reserved(name), "{} shouldn't be reserved", name);
+        }
+    }
+}
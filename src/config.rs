@@ -0,0 +1,358 @@
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use serenity::prelude::Context;
+use toml;
+use typemap::Key;
+use backup;
+use playground;
+use sqlite;
+use store;
+
+const CONFIG_PATH: &'static str = "config.toml";
+const PREFIX: &'static str = "~";
+
+/// The `[discord]` section of the config
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Discord {
+    pub token: String,
+    pub prefix: String,
+    /// Ids of the users that can use owner only commands
+    pub owners: Vec<u64>,
+}
+
+impl Default for Discord {
+    fn default() -> Discord {
+        Discord {
+            token: String::new(),
+            prefix: PREFIX.to_string(),
+            owners: Vec::new(),
+        }
+    }
+}
+
+/// The `[bot]` section of the config, shown by `~info`
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bot {
+    pub owner_name: String,
+    pub invite_link: String,
+}
+
+/// The `[home_guild]` section of the config, the only guild where commands can be added
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HomeGuild {
+    pub id: u64,
+    pub name: String,
+}
+
+/// The `[database]` section of the config
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Database {
+    /// `memory://` or a PostgreSQL url, the SQLite database is used if empty
+    pub url: String,
+}
+
+/// Settings read from `config.toml`, with environment variables overriding the file
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub discord: Discord,
+    pub bot: Bot,
+    pub home_guild: HomeGuild,
+    pub database: Database,
+    pub sqlite: sqlite::Settings,
+    pub backup: backup::Settings,
+    pub playground: playground::Settings,
+}
+
+impl Key for Config {
+    type Value = Arc<Config>;
+}
+
+// sets a value from an environment variable if it's set and not empty
+fn env_override<T>(key: &str, value: &mut T, problems: &mut Vec<String>)
+    where T: FromStr, T::Err: Display
+{
+    let raw = match env::var(key) {
+        Ok(ref raw) if raw.trim().is_empty() => return,
+        Ok(raw) => raw,
+        Err(_) => return,
+    };
+
+    match raw.trim().parse() {
+        Ok(parsed) => *value = parsed,
+        Err(why) => problems.push(format!("{}: invalid value {:?}: {}", key, raw, why)),
+    }
+}
+
+impl Config {
+    fn apply_env(&mut self, problems: &mut Vec<String>) {
+        env_override("DISCORD_TOKEN", &mut self.discord.token, problems);
+        env_override("PREFIX", &mut self.discord.prefix, problems);
+
+        let mut owners = String::new();
+        env_override("OWNERS", &mut owners, problems);
+
+        // a comma separated list of user ids
+        if !owners.is_empty() {
+            match owners.split(',').map(|id| id.trim().parse()).collect::<Result<Vec<u64>, _>>() {
+                Ok(ids) => self.discord.owners = ids,
+                Err(why) => problems.push(format!("OWNERS: invalid user id in {:?}: {}", owners, why)),
+            }
+        }
+
+        env_override("OWNER_NAME", &mut self.bot.owner_name, problems);
+        env_override("INVITE_LINK", &mut self.bot.invite_link, problems);
+        env_override("HOME_GUILD_ID", &mut self.home_guild.id, problems);
+        env_override("HOME_GUILD_NAME", &mut self.home_guild.name, problems);
+
+        env_override("DATABASE_URL", &mut self.database.url, problems);
+
+        let mut path = String::new();
+        env_override("DATABASE_PATH", &mut path, problems);
+        if !path.is_empty() {
+            self.sqlite.path = PathBuf::from(path);
+        }
+
+        env_override("DATABASE_BUSY_TIMEOUT", &mut self.sqlite.busy_timeout, problems);
+        env_override("DATABASE_READERS", &mut self.sqlite.readers, problems);
+
+        let mut dir = String::new();
+        env_override("BACKUP_DIR", &mut dir, problems);
+        if !dir.is_empty() {
+            self.backup.dir = PathBuf::from(dir);
+        }
+
+        env_override("BACKUP_RETENTION", &mut self.backup.retention, problems);
+        env_override("BACKUP_INTERVAL", &mut self.backup.interval, problems);
+
+        env_override("PLAYGROUND_URL", &mut self.playground.base_url, problems);
+        env_override("PLAYGROUND_TIMEOUT", &mut self.playground.timeout, problems);
+        env_override("PLAYGROUND_RETRIES", &mut self.playground.retries, problems);
+    }
+
+    fn validate(&self, problems: &mut Vec<String>) {
+        let mut require = |valid: bool, problem: &str| {
+            if !valid {
+                problems.push(problem.to_string());
+            }
+        };
+
+        require(!self.discord.token.is_empty(), "discord.token (DISCORD_TOKEN) is required");
+        require(!self.discord.prefix.is_empty() && !self.discord.prefix.contains(char::is_whitespace),
+                "discord.prefix (PREFIX) can't be empty or contain spaces");
+        require(!self.discord.owners.is_empty(), "discord.owners (OWNERS) needs at least one user id");
+
+        require(!self.bot.owner_name.is_empty(), "bot.owner_name (OWNER_NAME) is required");
+        require(!self.bot.invite_link.is_empty(), "bot.invite_link (INVITE_LINK) is required");
+
+        require(self.home_guild.id != 0, "home_guild.id (HOME_GUILD_ID) is required");
+        require(!self.home_guild.name.is_empty(), "home_guild.name (HOME_GUILD_NAME) is required");
+
+        require(!store::is_postgres_url(&self.database.url) || cfg!(feature = "postgres"),
+                "database.url (DATABASE_URL) is a PostgreSQL url but the bot was built without the \
+                 `postgres` feature");
+
+        require(self.sqlite.readers > 0, "sqlite.readers (DATABASE_READERS) must be at least 1");
+        require(self.backup.retention > 0, "backup.retention (BACKUP_RETENTION) must be at least 1");
+
+        let url = &self.playground.base_url;
+        require(url.starts_with("https://") || url.starts_with("http://"),
+                "playground.url (PLAYGROUND_URL) must be an http or https url");
+        require(self.playground.timeout > 0, "playground.timeout (PLAYGROUND_TIMEOUT) must be at least 1");
+    }
+}
+
+/// Loads the config from `CONFIG_PATH` or `config.toml`, applies the environment
+/// variables overriding it and validates it.  Returns every problem found
+/// instead of stopping at the first one.
+pub fn load() -> Result<Config, Vec<String>> {
+    // the default file is optional, settings can all come from the environment
+    let (path, required) = match env::var("CONFIG_PATH") {
+        Ok(ref path) if path.is_empty() => (CONFIG_PATH.to_string(), false),
+        Ok(path) => (path, true),
+        Err(_) => (CONFIG_PATH.to_string(), false),
+    };
+
+    let mut contents = String::new();
+    let read = File::open(&path).and_then(|mut file| file.read_to_string(&mut contents));
+
+    match read {
+        Ok(_) => {},
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound && !required => {},
+        Err(why) => return Err(vec![format!("{}: {}", path, why)]),
+    }
+
+    let mut config: Config = match toml::from_str(&contents) {
+        Ok(config) => config,
+        Err(why) => return Err(vec![format!("{}: {}", path, why)]),
+    };
+
+    let mut problems = Vec::new();
+    config.apply_env(&mut problems);
+    config.validate(&mut problems);
+
+    if problems.is_empty() {
+        Ok(config)
+    } else {
+        Err(problems)
+    }
+}
+
+/// Gets the config without holding the lock on the context data
+pub fn get(ctx: &Context) -> Arc<Config> {
+    let data = ctx.data.lock();
+    data.get::<Config>().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::*;
+
+    const FILE: &'static str = r#"
+        [discord]
+        token = "token"
+        prefix = "!"
+        owners = [1, 2]
+
+        [bot]
+        owner_name = "owner"
+        invite_link = "https://discord.com/invite"
+
+        [home_guild]
+        id = 10
+        name = "Home"
+
+        [sqlite]
+        readers = 2
+
+        [playground]
+        url = "http://localhost:8000"
+        timeout = 5
+    "#;
+
+    fn parse(contents: &str) -> Config {
+        toml::from_str(contents).unwrap()
+    }
+
+    fn problems(config: &Config) -> Vec<String> {
+        let mut problems = Vec::new();
+        config.validate(&mut problems);
+        problems
+    }
+
+    #[test]
+    fn files_are_parsed() {
+        let config = parse(FILE);
+
+        assert_eq!(config.discord.token, "token");
+        assert_eq!(config.discord.prefix, "!");
+        assert_eq!(config.discord.owners, vec![1, 2]);
+        assert_eq!(config.home_guild.id, 10);
+        assert_eq!(config.sqlite.readers, 2);
+        assert_eq!(config.playground.base_url, "http://localhost:8000");
+        assert_eq!(config.playground.timeout, 5);
+
+        // missing sections and keys keep their defaults
+        assert_eq!(config.sqlite.busy_timeout, sqlite::Settings::default().busy_timeout);
+        assert_eq!(config.backup.retention, backup::Settings::default().retention);
+        assert_eq!(config.playground.retries, playground::Settings::default().retries);
+        assert!(config.database.url.is_empty());
+
+        assert!(problems(&config).is_empty(), "{:?}", problems(&config));
+    }
+
+    #[test]
+    fn the_example_is_parsed() {
+        let config = parse(include_str!("../config.toml.example"));
+
+        assert_eq!(config.discord.prefix, PREFIX);
+        assert_eq!(config.sqlite.path, Path::new("database.sqlite3"));
+    }
+
+    #[test]
+    fn unknown_keys_and_wrong_types_are_rejected() {
+        assert!(toml::from_str::<Config>("[discord]\ntokn = \"token\"").is_err());
+        assert!(toml::from_str::<Config>("[website]\nurl = \"\"").is_err());
+        assert!(toml::from_str::<Config>("[home_guild]\nid = \"10\"").is_err());
+    }
+
+    // the only test touching the environment, as tests share it and run in parallel
+    #[test]
+    fn the_environment_overrides_the_file() {
+        let mut config = parse(FILE);
+
+        env::set_var("DISCORD_TOKEN", "from env");
+        env::set_var("PREFIX", "  ");
+        env::set_var("OWNERS", "3, 4");
+        env::set_var("HOME_GUILD_ID", "20");
+        env::set_var("DATABASE_PATH", "other.sqlite3");
+        env::set_var("PLAYGROUND_URL", "https://play.example.com");
+
+        let mut problems = Vec::new();
+        config.apply_env(&mut problems);
+
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(config.discord.token, "from env");
+        // blank variables are ignored
+        assert_eq!(config.discord.prefix, "!");
+        assert_eq!(config.discord.owners, vec![3, 4]);
+        assert_eq!(config.home_guild.id, 20);
+        assert_eq!(config.sqlite.path, Path::new("other.sqlite3"));
+        assert_eq!(config.playground.base_url, "https://play.example.com");
+        // ones that aren't set leave the file's values
+        assert_eq!(config.home_guild.name, "Home");
+
+        env::set_var("OWNERS", "3, four");
+        env::set_var("PLAYGROUND_TIMEOUT", "soon");
+
+        let mut problems = Vec::new();
+        config.apply_env(&mut problems);
+
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("OWNERS:"));
+        assert!(problems[1].starts_with("PLAYGROUND_TIMEOUT:"));
+        assert_eq!(config.discord.owners, vec![3, 4]);
+        assert_eq!(config.playground.timeout, 5);
+
+        for key in &["DISCORD_TOKEN", "PREFIX", "OWNERS", "HOME_GUILD_ID", "DATABASE_PATH", "PLAYGROUND_URL",
+                     "PLAYGROUND_TIMEOUT"] {
+            env::remove_var(key);
+        }
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let mut config = Config::default();
+        config.playground.base_url = "ftp://play.rust-lang.org".to_string();
+
+        let problems = problems(&config);
+
+        for problem in &["discord.token (DISCORD_TOKEN) is required",
+                         "home_guild.id (HOME_GUILD_ID) is required",
+                         "playground.url (PLAYGROUND_URL) must be an http or https url"] {
+            assert!(problems.iter().any(|p| p == problem), "{} is missing from {:?}", problem, problems);
+        }
+
+        // along with the owners, bot and home guild name
+        assert_eq!(problems.len(), 7, "{:?}", problems);
+    }
+
+    #[test]
+    fn prefixes_cant_contain_spaces() {
+        let mut config = parse(FILE);
+        config.discord.prefix = "! ".to_string();
+
+        assert_eq!(problems(&config), vec!["discord.prefix (PREFIX) can't be empty or contain spaces"]);
+    }
+}
@@ -0,0 +1,246 @@
+use serenity::CACHE;
+use serenity::prelude::*;
+use serenity::model::{Message, UserId};
+use commands;
+use store;
+use store::{CommandStore, CustomCommand, TriggerKind};
+use trigger;
+use actions;
+use locale;
+
+/// A custom command invocation parsed from a message
+pub struct Invocation {
+    /// The first word after the prefix
+    pub name: String,
+    /// Everything after the name, with the original spacing
+    pub rest: String,
+    pub args: Vec<String>,
+}
+
+// strips the prefix or a mention of the bot from the start of a message
+fn strip_prefix<'a>(content: &'a str, prefix: &str, bot_id: UserId) -> Option<&'a str> {
+    if content.starts_with(prefix) {
+        return Some(&content[prefix.len()..]);
+    }
+
+    for mention in &[format!("<@{}>", bot_id.0), format!("<@!{}>", bot_id.0)] {
+        if content.starts_with(mention.as_str()) {
+            return Some(&content[mention.len()..]);
+        }
+    }
+
+    None
+}
+
+/// Parses a message starting with the prefix or a mention of the bot into a
+/// command name and its arguments.
+pub fn parse(content: &str, prefix: &str, bot_id: UserId) -> Option<Invocation> {
+    let content = match strip_prefix(content, prefix, bot_id) {
+        Some(content) => content.trim_left(),
+        None => return None,
+    };
+
+    let name = match content.split_whitespace().next() {
+        Some(name) => name,
+        None => return None,
+    };
+
+    Some(Invocation::new(name, &content[name.len()..]))
+}
+
+impl Invocation {
+    fn new(name: &str, rest: &str) -> Invocation {
+        let rest = rest.trim();
+
+        Invocation {
+            name: name.to_string(),
+            rest: rest.to_string(),
+            args: rest.split_whitespace().map(|arg| arg.to_string()).collect(),
+        }
+    }
+}
+
+// keeps arguments from pinging through the bot, covers @everyone, @here and
+// user, nickname, role and channel mentions
+fn escape_mentions(text: &str) -> String {
+    text.replace("@everyone", "@\u{200B}everyone")
+        .replace("@here", "@\u{200B}here")
+        .replace("<@", "<@\u{200B}")
+        .replace("<#", "<#\u{200B}")
+}
+
+/// Fills in the placeholders of a response.
+///
+/// `{args}` is replaced with all arguments, `{1}` to `{9}` with single
+/// arguments and `{user}` with a mention of the author.
+pub fn render(response: &str, invocation: &Invocation, msg: &Message) -> String {
+    fill(response, invocation, &msg.author.mention())
+}
+
+// replaces placeholders in a single pass, so placeholders in the arguments
+// themselves are left as they are
+fn fill(response: &str, invocation: &Invocation, user: &str) -> String {
+    let mut rendered = String::with_capacity(response.len());
+    let mut rest = response;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let value = match &rest[1..end] {
+            "args" => Some(escape_mentions(&invocation.rest)),
+            "user" => Some(user.to_string()),
+            index if index.len() == 1 && index != "0" && index.chars().all(|c| c.is_digit(10)) => {
+                let i = index.parse::<usize>().unwrap();
+                Some(escape_mentions(invocation.args.get(i - 1).map_or("", |arg| arg.as_str())))
+            },
+            _ => None,
+        };
+
+        match value {
+            Some(value) => {
+                rendered.push_str(&value);
+                rest = &rest[end + 1..];
+            },
+            // not a placeholder, keep the brace and carry on after it
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+// sends the response of a command, runs its actions and counts the use
+fn respond(ctx: &Context, msg: &Message, db: &CommandStore, command: &CustomCommand, invocation: &Invocation) {
+    locale::use_for(ctx, msg);
+
+    match db.increment(command) {
+        Ok(_) => {}
+        Err(why) => {
+            error!(
+                "Error occurred when incrementing custom command count: {}",
+                why
+            )
+        }
+    }
+
+    debug!("Got custom command '{}' by user '{}'", command.name, msg.author.tag());
+
+    actions::run(ctx, msg, command, |text| render(text, invocation, msg));
+}
+
+/// Responds to messages invoking a custom command with the prefix or
+/// matching the triggers of custom commands
+pub fn handle_message(ctx: &Context, msg: &Message, prefix: &str) {
+    if msg.author.bot {
+        return;
+    }
+
+    let bot_id = CACHE.read().unwrap().user.id;
+    let db = store::get(ctx);
+
+    if let Some(invocation) = parse(&msg.content, prefix, bot_id) {
+        // handled by the framework
+        if commands::is_reserved(&invocation.name) {
+            return;
+        }
+
+        match db.get(&invocation.name) {
+            Ok(ref command) if command.trigger == TriggerKind::Prefix => {
+                return respond(ctx, msg, &*db, command, &invocation);
+            },
+            // no custom command found, the message can still match triggers
+            _ => {},
+        }
+    }
+
+    // triggers only respond in the guild they were set in
+    let guild_id = match msg.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+
+    let names = match trigger::get(ctx).matches(&*db, guild_id.0, msg.channel_id.0, &msg.content) {
+        Ok(names) => names,
+        Err(why) => return error!("Failed to load triggers: {}", why),
+    };
+
+    for name in names {
+        match db.get(&name) {
+            // the whole message is passed to triggered commands
+            Ok(command) => respond(ctx, msg, &*db, &command, &Invocation::new(&name, &msg.content)),
+            Err(why) => error!("Failed to get triggered command {}: {}", name, why),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTHOR: &'static str = "<@1>";
+
+    fn invocation(rest: &str) -> Invocation {
+        Invocation::new("cmd", rest)
+    }
+
+    #[test]
+    fn placeholders_are_filled() {
+        let invocation = invocation("first second");
+
+        assert_eq!(fill("{user}: {args} / {2} {1} {3}", &invocation, AUTHOR), "<@1>: first second / second first ");
+        assert_eq!(fill("no placeholders", &invocation, AUTHOR), "no placeholders");
+    }
+
+    #[test]
+    fn arguments_are_not_expanded_again() {
+        let invocation = invocation("{user} {2} {args}");
+
+        assert_eq!(fill("{1}", &invocation, AUTHOR), "{user}");
+        assert_eq!(fill("{args}", &invocation, AUTHOR), "{user} {2} {args}");
+        assert_eq!(fill("{1} {2}", &invocation, AUTHOR), "{user} {2}");
+    }
+
+    #[test]
+    fn other_braces_are_kept() {
+        let invocation = invocation("a");
+
+        assert_eq!(fill("{} {0} {10} {name} {{1}} {", &invocation, AUTHOR), "{} {0} {10} {name} {a} {");
+        assert_eq!(fill("fn main() { {1} }", &invocation, AUTHOR), "fn main() { a }");
+    }
+
+    #[test]
+    fn mentions_in_arguments_are_escaped() {
+        let invocation = invocation("@everyone @here <@1> <@!2> <@&3> <#4>");
+        let rendered = fill("{args}", &invocation, AUTHOR);
+
+        for mention in &["@everyone", "@here", "<@1>", "<@!2>", "<@&3>", "<#4>"] {
+            assert!(!rendered.contains(mention), "{} isn't escaped in {:?}", mention, rendered);
+        }
+
+        assert_eq!(fill("{4} {5}", &invocation, AUTHOR), "<@\u{200B}!2> <@\u{200B}&3>");
+        // the author mention comes from the bot and still pings
+        assert_eq!(fill("{user}", &invocation, AUTHOR), AUTHOR);
+    }
+
+    #[test]
+    fn parse_splits_name_and_arguments() {
+        let invocation = parse("~hello  there   world", "~", UserId(10)).unwrap();
+        assert_eq!(invocation.name, "hello");
+        assert_eq!(invocation.rest, "there   world");
+        assert_eq!(invocation.args, vec!["there", "world"]);
+
+        assert_eq!(parse("<@!10> hello", "~", UserId(10)).unwrap().name, "hello");
+        assert!(parse("hello", "~", UserId(10)).is_none());
+        assert!(parse("~", "~", UserId(10)).is_none());
+    }
+}
@@ -0,0 +1,174 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::result;
+use rusqlite;
+use reqwest;
+use serde_json;
+use serenity;
+use serenity::framework::standard::CommandError;
+use serenity::model::Message;
+#[cfg(feature = "postgres")]
+use r2d2_postgres::{postgres, r2d2};
+use helpers;
+use strings;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A custom command with the given name doesn't exist
+    NotFound(String),
+    /// A custom command with the given name already exists
+    AlreadyExists(String),
+    /// The name is used by a built-in command
+    Reserved(String),
+    /// The user isn't allowed to modify the command, with the message saying what they tried
+    PermissionDenied(strings::Error),
+    /// Missing or malformed arguments
+    InvalidInput(String),
+    /// The operation isn't supported by the command store in use
+    Unsupported,
+    Storage(rusqlite::Error),
+    #[cfg(feature = "postgres")]
+    Postgres(postgres::Error),
+    #[cfg(feature = "postgres")]
+    Pool(r2d2::Error),
+    Http(reqwest::Error),
+    Discord(serenity::Error),
+    Json(serde_json::Error),
+    Io(io::Error),
+}
+
+impl Error {
+    pub fn invalid_input<D: fmt::Display>(why: D) -> Error {
+        Error::InvalidInput(why.to_string())
+    }
+
+    /// Message shown to the user that triggered the error
+    pub fn user_message(&self) -> String {
+        match *self {
+            Error::NotFound(ref name) => helpers::get_error_f(strings::Error::CommandNotFound, &[("name", name)]),
+            Error::AlreadyExists(ref name) => helpers::get_error_f(strings::Error::CommandExists,
+                                                                   &[("name", name)]),
+            Error::Reserved(ref name) => helpers::get_error_f(strings::Error::CommandReserved, &[("name", name)]),
+            Error::PermissionDenied(message) => helpers::get_error(message),
+            Error::InvalidInput(ref why) => helpers::get_error_f(strings::Error::InvalidInput, &[("error", why)]),
+            Error::Unsupported => helpers::get_error(strings::Error::Unsupported),
+            Error::Storage(_) => helpers::get_error(strings::Error::Storage),
+            #[cfg(feature = "postgres")]
+            Error::Postgres(_) | Error::Pool(_) => helpers::get_error(strings::Error::Storage),
+            Error::Http(ref why) => helpers::get_error_f(strings::Error::Http, &[("error", why)]),
+            Error::Discord(_) => helpers::get_error(strings::Error::Discord),
+            Error::Json(ref why) => helpers::get_error_f(strings::Error::Json, &[("error", why)]),
+            Error::Io(_) => helpers::get_error(strings::Error::Io),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NotFound(ref name) => write!(f, "Command not found: {}", name),
+            Error::AlreadyExists(ref name) => write!(f, "Command already exists: {}", name),
+            Error::Reserved(ref name) => write!(f, "Name reserved by a built-in command: {}", name),
+            Error::PermissionDenied(_) => f.write_str("Permission denied"),
+            Error::InvalidInput(ref why) => write!(f, "Invalid input: {}", why),
+            Error::Unsupported => f.write_str("Unsupported by the command store"),
+            Error::Storage(ref why) => write!(f, "Storage error: {}", why),
+            #[cfg(feature = "postgres")]
+            Error::Postgres(ref why) => write!(f, "Postgres error: {}", why),
+            #[cfg(feature = "postgres")]
+            Error::Pool(ref why) => write!(f, "Connection pool error: {}", why),
+            Error::Http(ref why) => write!(f, "HTTP error: {}", why),
+            Error::Discord(ref why) => write!(f, "Discord error: {}", why),
+            Error::Json(ref why) => write!(f, "JSON error: {}", why),
+            Error::Io(ref why) => write!(f, "IO error: {}", why),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::NotFound(_) => "command not found",
+            Error::AlreadyExists(_) => "command already exists",
+            Error::Reserved(_) => "name reserved by a built-in command",
+            Error::PermissionDenied(_) => "permission denied",
+            Error::InvalidInput(_) => "invalid input",
+            Error::Unsupported => "unsupported by the command store",
+            Error::Storage(ref why) => why.description(),
+            #[cfg(feature = "postgres")]
+            Error::Postgres(ref why) => why.description(),
+            #[cfg(feature = "postgres")]
+            Error::Pool(ref why) => why.description(),
+            Error::Http(ref why) => why.description(),
+            Error::Discord(ref why) => why.description(),
+            Error::Json(ref why) => why.description(),
+            Error::Io(ref why) => why.description(),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(why: rusqlite::Error) -> Error {
+        Error::Storage(why)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<postgres::Error> for Error {
+    fn from(why: postgres::Error) -> Error {
+        Error::Postgres(why)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<r2d2::Error> for Error {
+    fn from(why: r2d2::Error) -> Error {
+        Error::Pool(why)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(why: reqwest::Error) -> Error {
+        Error::Http(why)
+    }
+}
+
+impl From<serenity::Error> for Error {
+    fn from(why: serenity::Error) -> Error {
+        Error::Discord(why)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(why: serde_json::Error) -> Error {
+        Error::Json(why)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(why: io::Error) -> Error {
+        Error::Io(why)
+    }
+}
+
+/// Replies with the user facing message of an error and converts it to be
+/// returned from a command, where it is logged by the framework's `after` hook.
+pub fn reply(msg: &Message, why: Error) -> CommandError {
+    let _ = msg.channel_id.say(why.user_message());
+
+    CommandError::from(why)
+}
+
+/// Unwraps a result in a command, replying to the message and returning
+/// early with the error if it failed.
+macro_rules! try_reply {
+    ($msg:expr, $expr:expr) => {
+        match $expr {
+            Ok(val) => val,
+            Err(why) => return Err(::error::reply($msg, ::error::Error::from(why))),
+        }
+    };
+}
@@ -1,63 +1,120 @@
-use serde_json;
-use serde_json::Value;
-use serde_json::Map;
-use std::fs::File;
-use std::io::prelude::*;
+use std::fmt::Display;
+use serenity::CACHE;
+use serenity::model::UserId;
+use locale;
+use strings;
+use strings::Text;
 
-lazy_static! {
-  static ref LOCALE: Locale = Locale::new();
+pub fn get_error(id: strings::Error) -> String {
+    locale::get(id, None)
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Locale {
-    pub info: Map<String, Value>,
-    pub error: Map<String, Value>,
+pub fn get_info(id: strings::Info) -> String {
+    locale::get(id, None)
 }
 
-impl Locale {
-    pub fn new() -> Locale {
-        let mut f = File::open("assets/locale.json").expect("Locale file not found.");
+// replaces each `{name}` placeholder with its value
+fn replace<T: Text>(id: T, text: String, replacements: &[(&str, &Display)]) -> String {
+    debug_assert!(id.placeholders().iter().all(|placeholder| {
+        replacements.iter().any(|&(name, _)| name == *placeholder)
+    }), "missing placeholders for {}", id.key());
 
-        let mut contents = String::new();
-        f.read_to_string(&mut contents).expect(
-            "Something went wrong reading the locale file.",
-        );
+    fill(&text, replacements)
+}
+
+// fills in placeholders in a single pass, so values containing placeholders
+// themselves are left as they are
+fn fill(text: &str, replacements: &[(&str, &Display)]) -> String {
+    let mut filled = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
 
-        serde_json::from_str(&contents).expect("Failed to parse JSON")
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+
+        match replacements.iter().find(|&&(name, _)| name == &rest[1..end]) {
+            Some(&(_, value)) => {
+                filled.push_str(&value.to_string());
+                rest = &rest[end + 1..];
+            },
+            // not a placeholder, keep the brace and carry on after it
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            },
+        }
     }
+
+    filled.push_str(rest);
+    filled
 }
 
-pub fn get_error(id: &str) -> String {
-    match LOCALE.error.get(id) {
-        Some(val) => val.as_str().unwrap_or("").to_string(),
-        None => "".to_string(),
-    }
+pub fn get_error_f(id: strings::Error, replacements: &[(&str, &Display)]) -> String {
+    replace(id, get_error(id), replacements)
 }
 
-pub fn get_info(id: &str) -> String {
-    match LOCALE.info.get(id) {
-        Some(val) => val.as_str().unwrap_or("").to_string(),
-        None => "".to_string(),
-    }
+pub fn get_info_f(id: strings::Info, replacements: &[(&str, &Display)]) -> String {
+    replace(id, get_info(id), replacements)
 }
 
-fn replace(text: String, replacements: &[&String]) -> String {
-    let mut new_string = text;
-    for repl in replacements {
-        new_string = new_string.replacen("{}", repl, 1); // replace each single {} with replacements
-    }
+/// Gets a string with plural forms, using the one for `count` and replacing
+/// `{count}` along with the other placeholders
+pub fn get_error_n(id: strings::Error, count: u64, replacements: &[(&str, &Display)]) -> String {
+    replace_count(id, locale::get(id, Some(count)), count, replacements)
+}
 
-    new_string.to_string()
+pub fn get_info_n(id: strings::Info, count: u64, replacements: &[(&str, &Display)]) -> String {
+    replace_count(id, locale::get(id, Some(count)), count, replacements)
 }
 
-pub fn get_error_f(id: &str, replacements: &[&String]) -> String {
-    let text = get_error(id);
+fn replace_count<T: Text>(id: T, text: String, count: u64, replacements: &[(&str, &Display)]) -> String {
+    let mut all: Vec<(&str, &Display)> = replacements.to_vec();
+    all.push(("count", &count));
 
-    replace(text, &replacements)
+    replace(id, text, &all)
 }
 
-pub fn get_info_f(id: &str, replacements: &[&String]) -> String {
-    let text = get_info(id);
+/// Resolves a user id to their tag, falling back to a mention.  Users in the
+/// cache are used before requesting them from Discord.
+pub fn user_tag(id: i64) -> String {
+    let id = UserId(id as u64);
+
+    if let Some(user) = CACHE.read().unwrap().user(id) {
+        return user.read().unwrap().tag();
+    }
 
-    replace(text, &replacements)
+    match id.get() {
+        Ok(user) => user.tag(),
+        Err(_) => format!("<@{}>", id.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_filled() {
+        assert_eq!(fill("`{name}` has {count} uses", &[("name", &"hi"), ("count", &3)]), "`hi` has 3 uses");
+        assert_eq!(fill("{name} and {name}", &[("name", &"a")]), "a and a");
+        assert_eq!(fill("no placeholders", &[("name", &"a")]), "no placeholders");
+    }
+
+    #[test]
+    fn values_are_not_filled_again() {
+        let replacements: &[(&str, &Display)] = &[("name", &"{response}"), ("response", &"{name}")];
+
+        assert_eq!(fill("{name}: {response}", replacements), "{response}: {name}");
+        assert_eq!(fill("{response}", &[("response", &"{response}")]), "{response}");
+    }
+
+    #[test]
+    fn other_braces_are_kept() {
+        assert_eq!(fill("{} {unknown} {{name}} {", &[("name", &"a")]), "{} {unknown} {a} {");
+    }
 }
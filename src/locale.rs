@@ -0,0 +1,377 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use serde_json;
+use serde_json::{Map, Value};
+use serenity::prelude::Context;
+use serenity::model::Message;
+use typemap::Key;
+use error::{Error, Result};
+use store;
+use store::CommandStore;
+use strings;
+use strings::Text;
+
+const LOCALES_DIR: &'static str = "assets/locales";
+
+/// Language used for guilds that haven't chosen one, and for any strings
+/// missing from the language they chose
+pub const DEFAULT_LANGUAGE: &'static str = "en";
+
+// guild languages are reloaded after this long in case another instance
+// sharing the database changed them
+const TTL_SECS: u64 = 60;
+
+// forms a string can have instead of a single text so it can change with a
+// count, `other` is required and used for any count without its own form
+const PLURAL_FORMS: &'static [&'static str] = &["zero", "one", "other"];
+
+/// Strings of a single language, read from `assets/locales/<language>.json`
+#[derive(Deserialize)]
+struct Locale {
+    #[serde(default)]
+    info: Map<String, Value>,
+    #[serde(default)]
+    error: Map<String, Value>,
+}
+
+impl Locale {
+    fn section(&self, section: &str) -> &Map<String, Value> {
+        match section {
+            "info" => &self.info,
+            _ => &self.error,
+        }
+    }
+}
+
+lazy_static! {
+    static ref LOCALES: RwLock<HashMap<String, Locale>> = RwLock::new(HashMap::new());
+}
+
+thread_local! {
+    // language of the message being handled on this thread
+    static LANGUAGE: RefCell<String> = RefCell::new(DEFAULT_LANGUAGE.to_string());
+}
+
+fn load(path: &Path) -> Result<Locale> {
+    let mut contents = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut contents));
+
+    serde_json::from_str(&contents)
+        .map_err(|why| Error::invalid_input(format!("{}: {}", path.display(), why)))
+}
+
+// names of the `{name}` placeholders in a string
+fn placeholders(text: &str) -> Vec<&str> {
+    let mut placeholders = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+
+        if let Some(end) = rest.find('}') {
+            let name = &rest[..end];
+
+            if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                placeholders.push(name);
+            }
+        }
+    }
+
+    placeholders.sort();
+    placeholders.dedup();
+
+    placeholders
+}
+
+// the text of a string, picking the plural form for the count if it has them
+fn text(val: &Value, count: Option<u64>) -> Option<&str> {
+    match *val {
+        Value::String(ref text) => Some(text),
+        Value::Object(ref forms) => {
+            let form = match count {
+                Some(0) if forms.contains_key("zero") => "zero",
+                Some(1) if forms.contains_key("one") => "one",
+                _ => "other",
+            };
+
+            forms.get(form).and_then(|text| text.as_str())
+        },
+        _ => None,
+    }
+}
+
+// problems with the placeholders of a string, plural forms can leave out the count
+fn check_placeholders<T: Text>(language: &str, id: T, val: &Value, problems: &mut Vec<String>) {
+    let mut expected = id.placeholders().to_vec();
+    expected.sort();
+
+    let without_count: Vec<&str> = expected.iter().cloned().filter(|name| *name != "count").collect();
+
+    let forms: Vec<(&str, &Value)> = match *val {
+        Value::Object(ref forms) => {
+            if !forms.contains_key("other") {
+                problems.push(format!("{}: {}.{} is missing the plural form other",
+                                      language, T::section(), id.key()));
+            }
+
+            forms.iter().map(|(form, text)| (form.as_str(), text)).collect()
+        },
+        _ => vec![("other", val)],
+    };
+
+    for (form, text) in forms {
+        let text = match text.as_str() {
+            Some(text) if PLURAL_FORMS.iter().any(|plural| *plural == form) => text,
+            _ => {
+                problems.push(format!("{}: {}.{} has an invalid plural form {}",
+                                      language, T::section(), id.key(), form));
+                continue;
+            },
+        };
+
+        let actual = placeholders(text);
+
+        if actual != expected && (val.is_string() || actual != without_count) {
+            problems.push(format!("{}: {}.{} has placeholders {:?} instead of {:?}",
+                                  language, T::section(), id.key(), actual, expected));
+        }
+    }
+}
+
+// problems with the strings of one section of a locale compared to the keys in code
+fn check_section<T: Text>(language: &str, locale: &Locale, problems: &mut Vec<String>) {
+    let strings = locale.section(T::section());
+
+    for id in T::all() {
+        match strings.get(id.key()) {
+            Some(val) => check_placeholders(language, *id, val, problems),
+            None => problems.push(format!("{}: missing {}.{}", language, T::section(), id.key())),
+        }
+    }
+
+    for key in strings.keys().filter(|key| !T::all().iter().any(|id| id.key() == key.as_str())) {
+        problems.push(format!("{}: extra {}.{}", language, T::section(), key));
+    }
+}
+
+fn check(language: &str, locale: &Locale) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    check_section::<strings::Info>(language, locale, &mut problems);
+    check_section::<strings::Error>(language, locale, &mut problems);
+
+    problems
+}
+
+// loads the locales, failing if the default one has problems as there would
+// be nothing to fall back to
+fn load_all() -> Result<(HashMap<String, Locale>, Vec<String>)> {
+    let mut locales = HashMap::new();
+    let mut problems = Vec::new();
+
+    for entry in try!(fs::read_dir(LOCALES_DIR)) {
+        let path = try!(entry).path();
+
+        if path.extension().map_or(true, |ext| ext != "json") {
+            continue;
+        }
+
+        let language = match path.file_stem().and_then(|stem| stem.to_str()) {
+            Some(language) => language.to_string(),
+            None => continue,
+        };
+
+        let locale = try!(load(&path));
+        let locale_problems = check(&language, &locale);
+
+        if language == DEFAULT_LANGUAGE && !locale_problems.is_empty() {
+            return Err(Error::invalid_input(locale_problems.join(", ")));
+        }
+
+        problems.extend(locale_problems);
+        locales.insert(language, locale);
+    }
+
+    if !locales.contains_key(DEFAULT_LANGUAGE) {
+        return Err(Error::invalid_input(format!("missing {}/{}.json", LOCALES_DIR, DEFAULT_LANGUAGE)));
+    }
+
+    problems.sort();
+
+    Ok((locales, problems))
+}
+
+/// Loads every locale in `assets/locales`, replacing the ones in use if they
+/// all load.  Returns the strings that are missing, extra or have the wrong
+/// placeholders in languages other than English, which fall back to English.
+pub fn reload() -> Result<Vec<String>> {
+    let (locales, problems) = try!(load_all());
+
+    info!("Loaded {} locales", locales.len());
+    *LOCALES.write().unwrap() = locales;
+
+    Ok(problems)
+}
+
+/// Languages that have a locale, sorted
+pub fn languages() -> Vec<String> {
+    let mut languages: Vec<String> = LOCALES.read().unwrap().keys().cloned().collect();
+    languages.sort();
+
+    languages
+}
+
+pub fn exists(language: &str) -> bool {
+    LOCALES.read().unwrap().contains_key(language)
+}
+
+/// Gets a string in the language used on this thread, falling back to the
+/// default language if it's missing.  Strings with plural forms use the one
+/// for `count`, or `other` if there's no count.
+pub fn get<T: Text>(id: T, count: Option<u64>) -> String {
+    let locales = LOCALES.read().unwrap();
+
+    let find = |language: &str| {
+        locales.get(language)
+            .and_then(|locale| locale.section(T::section()).get(id.key()))
+            .and_then(|val| text(val, count))
+            .map(|text| text.to_string())
+    };
+
+    find(&language()).or_else(|| find(DEFAULT_LANGUAGE)).unwrap_or_default()
+}
+
+/// The language of the strings used on this thread
+pub fn language() -> String {
+    LANGUAGE.with(|language| language.borrow().clone())
+}
+
+/// Sets the language of the strings used on this thread
+pub fn set_language(language: &str) {
+    LANGUAGE.with(|current| *current.borrow_mut() = language.to_string());
+}
+
+/// Languages chosen by guilds, loaded from the store when first needed
+pub struct Languages {
+    guilds: RwLock<Option<(Arc<HashMap<u64, String>>, Instant)>>
+}
+
+impl Key for Languages {
+    type Value = Arc<Languages>;
+}
+
+impl Languages {
+    pub fn new() -> Languages {
+        Languages { guilds: RwLock::new(None) }
+    }
+
+    /// Drops the loaded languages so they are reloaded for the next message
+    pub fn invalidate(&self) {
+        *self.guilds.write().unwrap() = None;
+    }
+
+    fn current(&self, store: &CommandStore) -> Result<Arc<HashMap<u64, String>>> {
+        if let Some((ref guilds, ref loaded)) = *self.guilds.read().unwrap() {
+            if loaded.elapsed() < Duration::from_secs(TTL_SECS) {
+                return Ok(guilds.clone());
+            }
+        }
+
+        let guilds = Arc::new(try!(store.guild_languages()).into_iter().collect::<HashMap<_, _>>());
+        *self.guilds.write().unwrap() = Some((guilds.clone(), Instant::now()));
+
+        Ok(guilds)
+    }
+
+    /// Language chosen by a guild, the default if it hasn't chosen one
+    pub fn guild(&self, store: &CommandStore, guild: u64) -> Result<String> {
+        let guilds = try!(self.current(store));
+
+        Ok(guilds.get(&guild).cloned().unwrap_or(DEFAULT_LANGUAGE.to_string()))
+    }
+}
+
+/// Gets the guild languages without holding the lock on the context data
+pub fn get_languages(ctx: &Context) -> Arc<Languages> {
+    let data = ctx.data.lock();
+    data.get::<Languages>().unwrap().clone()
+}
+
+/// Uses the language of the guild a message was sent in for the strings used
+/// on this thread while handling it
+pub fn use_for(ctx: &Context, msg: &Message) {
+    let language = match msg.guild_id() {
+        Some(guild_id) => {
+            get_languages(ctx).guild(&*store::get(ctx), guild_id.0).unwrap_or_else(|why| {
+                warn!("Failed to get the language of guild {}: {}", guild_id, why);
+                DEFAULT_LANGUAGE.to_string()
+            })
+        },
+        None => DEFAULT_LANGUAGE.to_string(),
+    };
+
+    set_language(&language);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+
+    fn locale(json: &str) -> Locale {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn english_has_every_string() {
+        let path = PathBuf::from(LOCALES_DIR).join(format!("{}.json", DEFAULT_LANGUAGE));
+        let english = load(&path).unwrap();
+
+        assert_eq!(check(DEFAULT_LANGUAGE, &english), Vec::<String>::new());
+    }
+
+    #[test]
+    fn every_locale_loads() {
+        let (locales, _) = load_all().unwrap();
+        assert!(locales.contains_key(DEFAULT_LANGUAGE));
+    }
+
+    #[test]
+    fn problems_are_reported() {
+        let problems = check("xx", &locale(r#"{
+            "info": {"page_footer": "Page {page}", "unused": "text"},
+            "error": {"command_not_found": {"one": "No {name}"}}
+        }"#));
+
+        assert!(problems.contains(&"xx: missing info.pong".to_string()));
+        assert!(problems.contains(&"xx: extra info.unused".to_string()));
+        assert!(problems.iter().any(|problem| problem.starts_with("xx: info.page_footer has placeholders")));
+        assert!(problems.contains(&"xx: error.command_not_found is missing the plural form other".to_string()));
+    }
+
+    #[test]
+    fn placeholders_are_found() {
+        assert_eq!(placeholders("{b} and {a} and {b}"), vec!["a", "b"]);
+        assert_eq!(placeholders("{} { x } {not-one} {ok_1}"), vec!["ok_1"]);
+        assert!(placeholders("no braces {").is_empty());
+    }
+
+    #[test]
+    fn plural_forms_are_picked_by_count() {
+        let val: Value = serde_json::from_str(r#"{"zero": "none", "one": "one", "other": "many"}"#).unwrap();
+
+        assert_eq!(text(&val, Some(0)), Some("none"));
+        assert_eq!(text(&val, Some(1)), Some("one"));
+        assert_eq!(text(&val, Some(5)), Some("many"));
+        assert_eq!(text(&val, None), Some("many"));
+
+        let val = Value::String("plain".to_string());
+        assert_eq!(text(&val, Some(1)), Some("plain"));
+    }
+}
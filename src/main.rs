@@ -13,66 +13,71 @@ extern crate typemap;
 extern crate serde_derive;
 extern crate serde;
 extern crate serde_json;
+extern crate toml;
 
 #[macro_use]
 extern crate lazy_static;
 extern crate reqwest;
+extern crate unicode_segmentation;
+extern crate regex;
+#[cfg(feature = "postgres")]
+extern crate r2d2_postgres;
 
+#[macro_use]
+mod error;
 mod commands;
+mod config;
 mod sqlite;
+mod store;
+#[cfg(feature = "postgres")]
+mod postgres;
 mod helpers;
+mod pagination;
+mod chunk;
+mod backup;
+mod dispatch;
+mod trigger;
+mod actions;
+mod script;
+mod playground;
+mod rerun;
+mod locale;
+mod strings;
 
 use serenity::prelude::*;
 use serenity::model::*;
 use serenity::framework::StandardFramework;
-use serenity::framework::standard::help_commands;
 use dotenv::dotenv;
 use std::env;
-use typemap::Key;
-use sqlite::Database;
-
-const PREFIX: &'static str = "~";
-
-struct Handler;
-
-impl Key for Database {
-    type Value = Database;
+use std::process;
+use store::Store;
+use pagination::Pagers;
+use trigger::Triggers;
+use script::Scripts;
+use rerun::Runs;
+use locale::Languages;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+struct Handler {
+    prefix: String,
 }
 
 impl EventHandler for Handler {
     fn on_message(&self, ctx: Context, msg: Message) {
-        if msg.content.starts_with(PREFIX) {
-            let mut data = ctx.data.lock();
-            let db = data.get_mut::<sqlite::Database>().unwrap();
-
-            let command = match db.get(&msg.content[1..].to_string()) {
-                Ok(val) => val,
-                _ => {
-                    // no custom command found
-                    return;
-                }
-            };
-
-            match db.increment(&command) {
-                Ok(_) => {}
-                Err(why) => {
-                    error!(
-                        "Error occurred when incrementing custom command count: {}",
-                        why
-                    )
-                }
-            }
+        dispatch::handle_message(&ctx, &msg, &self.prefix);
+    }
 
-            println!(
-                "Got custom command '{}' by user '{}'",
-                command.name,
-                msg.author.tag()
-            );
+    fn on_message_update(&self, ctx: Context, event: event::MessageUpdateEvent) {
+        rerun::handle_update(&ctx, &event, &self.prefix);
+    }
 
-            if let Err(why) = msg.channel_id.say(command.url) {
-                error!("Error when sending message: {:?}", why);
-            }
-        }
+    fn on_message_delete(&self, ctx: Context, channel_id: ChannelId, message_id: MessageId) {
+        rerun::handle_delete(&ctx, channel_id, message_id);
+    }
+
+    fn on_reaction_add(&self, ctx: Context, reaction: Reaction) {
+        pagination::handle_reaction(&ctx, &reaction);
     }
 
     fn on_ready(&self, _: Context, ready: Ready) {
@@ -83,132 +88,109 @@ impl EventHandler for Handler {
 fn main() {
     dotenv().ok();
 
+    // validate the config and exit without connecting
+    if env::args().any(|arg| arg == "--check-config") {
+        match config::load() {
+            Ok(_) => println!("Config is valid"),
+            Err(problems) => {
+                for problem in problems {
+                    println!("{}", problem);
+                }
+
+                process::exit(1);
+            },
+        }
+
+        return;
+    }
+
     let _ = env_logger::init();
     info!("Starting...");
 
-    // Configure the client with your Discord bot token in the environment.
-    let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
-    let mut client = Client::new(&token, Handler);
+    let config = match config::load() {
+        Ok(config) => Arc::new(config),
+        Err(problems) => return error!("Invalid config:\n{}", problems.join("\n")),
+    };
+
+    match locale::reload() {
+        Ok(problems) => {
+            for problem in problems {
+                warn!("Locale {}", problem);
+            }
+        },
+        Err(why) => return error!("Failed to load locales: {}", why),
+    }
+
+    let mut client = Client::new(&config.discord.token, Handler { prefix: config.discord.prefix.clone() });
 
     {
         let mut data = client.data.lock();
 
-        match sqlite::connect() {
-            Ok(db) => data.insert::<Database>(db),
-            Err(_) => return error!("Failed to connect to database"),
+        match store::connect(&config.database.url, &config.sqlite) {
+            Ok(db) => {
+                backup::schedule(db.clone(), config.backup.clone());
+                data.insert::<Store>(db)
+            },
+            Err(why) => return error!("Failed to connect to database: {}", why),
         };
+
+        data.insert::<Pagers>(HashMap::new());
+        data.insert::<Runs>(HashMap::new());
+        data.insert::<Triggers>(Arc::new(Triggers::new()));
+        data.insert::<Scripts>(Arc::new(Scripts::new()));
+        data.insert::<Languages>(Arc::new(Languages::new()));
+
+        match playground::Client::new(config.playground.clone()) {
+            Ok(client) => data.insert::<playground::Client>(Arc::new(client)),
+            Err(why) => return error!("Failed to create playground client: {}", why),
+        };
+
+        data.insert::<config::Config>(config.clone());
     }
 
-    let invite_link = env::var("INVITE_LINK").expect("Expected INVITE_LINK in the environment");
-    let owner_name = env::var("OWNER_NAME").expect("Expected OWNER_NAME in the environment");
-    let home_guild_name =
-        env::var("HOME_GUILD_NAME").expect("Expected HOME_GUILD_NAME in the environment");
-
-    client.with_framework(
-        StandardFramework::new()
-            .configure(|c| {
-                c.prefix(PREFIX)
-                .owners(
-                    vec![UserId(150443906511667200)]
-                        .into_iter()
-                        .collect(),
-                )
-                .ignore_bots(true)
-                .on_mention(true)
-            })
-            .before(|_ctx, msg, command_name| {
-                println!(
-                    "Got command '{}' by user '{}'",
-                    command_name,
-                    msg.author.tag()
-                );
-
-                true // if `before` returns false, command processing doesn't happen.
-            })
-            .after(|_, _, command_name, error| match error {
-                Ok(()) => {}
-                Err(why) => println!("Command '{}' returned error {:?}", command_name, why),
-            })
-            .group("Meta", |g| {
-                g.command("help", |c| c.exec_help(help_commands::with_embeds))
-                    .command("ping", |c| c.exec_str("Pong!"))
-                    .command("latency", |c| {
-                        c.desc(
-                            "Calculates the heartbeat latency between the shard and the gateway.",
-                        ).exec(commands::meta::latency)
-                    })
-                    .command("info", |c| {
-                        c.desc("Gives info about the bot.").exec_str(&format!(
-                            "Hi!  I'm a bot written by {} with Rust and serenity-rs.\n\
-                    If you'd like to add me to another server, here's an invite link: <{}>\n\
-                    Commands can be only added in the {} server though!",
-                            owner_name,
-                            invite_link,
-                            home_guild_name
-                        ))
-                    })
-                    .command("shutdown", |c| {
-                        c.desc("Gracefully shuts down the bot.")
-                            .owners_only(true)
-                            .exec(commands::meta::shutdown)
-                    })
-            })
-            .group("Custom Commands", |g| {
-                g.command("commands", |c| {
-                    c.desc("Lists all available commands").exec(
-                        commands::custom_commands::commands,
-                    )
-                }).command("top", |c| {
-                        c.desc("Lists the top 10 most used commands").exec(
-                            commands::custom_commands::top,
-                        )
-                    })
-                    .command("add", |c| {
-                        c.usage("[name] [url]").desc("Adds a custom command").exec(
-                            commands::custom_commands::add,
-                        )
-                    })
-                    .command("delete", |c| {
-                        c.usage("[name]")
-                            .desc(
-                                "Deletes a custom command.  Limited to the
-                    creator of a command or members with MANAGE_GUILD permissions.",
-                            )
-                            .exec(commands::custom_commands::delete)
-                    })
-                    .command("edit", |c| {
-                        c.usage("[name] [new name] [new url]")
-                            .desc(
-                                "Edits an existing command.  Limited to the
-                    creator of a command or members with MANAGE_GUILD permissions.",
-                            )
-                            .exec(commands::custom_commands::edit)
-                    })
-                    .command("stat", |c| {
-                        c.usage("[name]")
-                            .desc("Shows information about a custom command.")
-                            .exec(commands::custom_commands::stat)
-                    })
-                    .command("search", |c| {
-                        c.usage("[name]")
-                            .desc("Searches for a custom command.")
-                            .exec(commands::custom_commands::search)
-                    })
-                    .command("import", |c| {
-                        c.usage("[json data]")
-                            .desc("Imports commands from json.")
-                            .owners_only(true)
-                            .exec(commands::custom_commands::import)
-                    })
-            })
-            .group("Misc", |g| {
-                g.command("play", |c| {
-                    c.usage("[rust code]")
-                        .desc("Evaluates Rust code in the playground.")
-                        .exec(commands::misc::play)
-                })
-            }),
-    );
+    let framework = StandardFramework::new()
+        .configure(|c| {
+            c.prefix(&config.discord.prefix)
+            .owners(
+                config.discord.owners
+                    .iter()
+                    .map(|&id| UserId(id))
+                    .collect(),
+            )
+            .ignore_bots(true)
+            .on_mention(true)
+        })
+        .before(|ctx, msg, command_name| {
+            println!(
+                "Got command '{}' by user '{}'",
+                command_name,
+                msg.author.tag()
+            );
+
+            locale::use_for(ctx, msg);
+
+            true // if `before` returns false, command processing doesn't happen.
+        })
+        .after(|_, msg, command_name, error| match error {
+            Ok(()) => {}
+            Err(why) => error!(
+                "Command '{}' by user '{}' in channel {} ({:?}) returned error {:?}",
+                command_name,
+                msg.author.tag(),
+                msg.channel_id,
+                msg.content,
+                why
+            ),
+        });
+
+    // registers the built-in commands, the same table reserves their names for custom commands
+    let framework = commands::GROUPS.iter().fold(framework, |framework, &(name, builtins)| {
+        framework.group(name, |g| {
+            builtins.iter().fold(g, |g, builtin| g.command(builtin.name, |c| builtin.create(c)))
+        })
+    });
+
+    client.with_framework(framework);
 
     if let Err(why) = client.start() {
         error!("Client error: {:?}", why);
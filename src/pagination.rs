@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+use serenity::prelude::*;
+use serenity::builder::CreateEmbed;
+use serenity::model::{ChannelId, Message, MessageId, Reaction, ReactionType, UserId};
+use typemap::Key;
+use helpers;
+use strings;
+use locale;
+
+pub const PREVIOUS: &'static str = "◀";
+pub const NEXT: &'static str = "▶";
+
+// how long a pager stays interactive after the last page change
+const TIMEOUT_SECS: u64 = 120;
+
+#[derive(Clone)]
+pub struct Pager {
+    owner: UserId,
+    title: String,
+    pages: Vec<String>,
+    current: usize,
+    // language of the guild it was sent in, reactions are handled on other threads
+    language: String,
+    last_active: Instant,
+}
+
+impl Pager {
+    fn embed(&self, e: CreateEmbed) -> CreateEmbed {
+        let current = (self.current + 1).to_string();
+        let total = self.pages.len().to_string();
+
+        e.title(&self.title)
+            .description(&self.pages[self.current])
+            .footer(|f| f.text(helpers::get_info_f(strings::Info::PageFooter,
+                                                   &[("page", &current), ("pages", &total)])))
+    }
+
+    // moves to the next or previous page, returns false if already at the end
+    fn turn(&mut self, forward: bool) -> bool {
+        if forward && self.current + 1 < self.pages.len() {
+            self.current += 1;
+        } else if !forward && self.current > 0 {
+            self.current -= 1;
+        } else {
+            return false;
+        }
+
+        self.last_active = Instant::now();
+        true
+    }
+}
+
+/// Active pagers keyed by the id of the message displaying them
+pub struct Pagers;
+
+impl Key for Pagers {
+    type Value = HashMap<MessageId, Pager>;
+}
+
+/// Sends the pages as an embed in the channel of `msg`, adding reaction
+/// controls that only the author of `msg` can use if there is more than one page.
+pub fn send(ctx: &Context, msg: &Message, title: &str, pages: Vec<String>) {
+    if pages.is_empty() {
+        return;
+    }
+
+    let pager = Pager {
+        owner: msg.author.id,
+        title: title.to_string(),
+        pages: pages,
+        current: 0,
+        language: locale::language(),
+        last_active: Instant::now(),
+    };
+
+    let sent = match msg.channel_id.send_message(|m| m.embed(|e| pager.embed(e))) {
+        Ok(val) => val,
+        Err(why) => {
+            return error!("Error when sending pager: {:?}", why);
+        }
+    };
+
+    // nothing to page through
+    if pager.pages.len() == 1 {
+        return;
+    }
+
+    let _ = sent.react(PREVIOUS);
+    let _ = sent.react(NEXT);
+
+    {
+        let mut data = ctx.data.lock();
+        data.get_mut::<Pagers>().unwrap().insert(sent.id, pager);
+    }
+
+    let data = ctx.data.clone();
+    let channel_id = sent.channel_id;
+    let message_id = sent.id;
+
+    // remove the controls once the pager has been idle for long enough
+    thread::spawn(move || {
+        let timeout = Duration::from_secs(TIMEOUT_SECS);
+
+        loop {
+            let remaining = {
+                let mut data = data.lock();
+                let pagers = data.get_mut::<Pagers>().unwrap();
+
+                let elapsed = match pagers.get(&message_id) {
+                    Some(pager) => pager.last_active.elapsed(),
+                    None => return,
+                };
+
+                if elapsed >= timeout {
+                    pagers.remove(&message_id);
+                    break;
+                }
+
+                timeout - elapsed
+            };
+
+            thread::sleep(remaining);
+        }
+
+        remove_controls(channel_id, message_id);
+    });
+}
+
+fn remove_controls(channel_id: ChannelId, message_id: MessageId) {
+    let removed = channel_id
+        .message(message_id)
+        .and_then(|message| message.delete_reactions());
+
+    // missing manage messages permission, at least remove our own reactions
+    if removed.is_err() {
+        let _ = channel_id.delete_reaction(message_id, None, PREVIOUS);
+        let _ = channel_id.delete_reaction(message_id, None, NEXT);
+    }
+}
+
+/// Turns the page of a pager if the reaction is a control added by its owner.
+pub fn handle_reaction(ctx: &Context, reaction: &Reaction) {
+    let forward = match reaction.emoji {
+        ReactionType::Unicode(ref emoji) if emoji == NEXT => true,
+        ReactionType::Unicode(ref emoji) if emoji == PREVIOUS => false,
+        _ => return,
+    };
+
+    // copy the page out so the data lock isn't held during the requests below
+    let pager = {
+        let mut data = ctx.data.lock();
+        let pagers = data.get_mut::<Pagers>().unwrap();
+
+        let pager = match pagers.get_mut(&reaction.message_id) {
+            Some(pager) => pager,
+            None => return,
+        };
+
+        // only the user who requested the list can control it
+        if reaction.user_id != pager.owner {
+            return;
+        }
+
+        if pager.turn(forward) {
+            Some(pager.clone())
+        } else {
+            None
+        }
+    };
+
+    // remove the reaction so the control can be used again
+    let _ = reaction.channel_id.delete_reaction(
+        reaction.message_id,
+        Some(reaction.user_id),
+        reaction.emoji.clone(),
+    );
+
+    let pager = match pager {
+        Some(pager) => pager,
+        None => return,
+    };
+
+    locale::set_language(&pager.language);
+
+    if let Err(why) = reaction
+        .channel_id
+        .edit_message(reaction.message_id, |m| m.embed(|e| pager.embed(e)))
+    {
+        error!("Error when editing pager: {:?}", why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chunk;
+
+    fn pager(pages: usize) -> Pager {
+        Pager {
+            owner: UserId(1),
+            title: "Commands".to_string(),
+            pages: (0..pages).map(|i| format!("page {}", i)).collect(),
+            current: 0,
+            language: locale::DEFAULT_LANGUAGE.to_string(),
+            last_active: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn turn_stops_at_the_ends() {
+        let mut pager = pager(3);
+
+        assert!(!pager.turn(false));
+        assert_eq!(pager.current, 0);
+
+        assert!(pager.turn(true));
+        assert!(pager.turn(true));
+        assert_eq!(pager.current, 2);
+
+        assert!(!pager.turn(true));
+        assert_eq!(pager.current, 2);
+
+        assert!(pager.turn(false));
+        assert_eq!(pager.current, 1);
+    }
+
+    #[test]
+    fn single_page_doesnt_turn() {
+        let mut pager = pager(1);
+
+        assert!(!pager.turn(true));
+        assert!(!pager.turn(false));
+        assert_eq!(pager.current, 0);
+    }
+
+    #[test]
+    fn turning_keeps_the_pager_active() {
+        let mut pager = pager(2);
+        let before = Instant::now() - Duration::from_secs(TIMEOUT_SECS);
+        pager.last_active = before;
+
+        assert!(pager.turn(true));
+        assert!(pager.last_active > before);
+
+        // a control that doesn't turn the page doesn't extend the timeout
+        pager.last_active = before;
+        assert!(!pager.turn(true));
+        assert_eq!(pager.last_active, before);
+    }
+
+    #[test]
+    fn command_list_pages_fit_in_an_embed() {
+        // long names with multi-codepoint graphemes, measured in characters rather than bytes
+        let list: Vec<String> = (0..500).map(|i| format!("{} - コマンド👨‍👩‍👧{}", i, i)).collect();
+        let pages = chunk::chunk_code_block(&list.join("\n"), "", chunk::EMBED_DESCRIPTION_LIMIT);
+
+        assert!(pages.len() > 1);
+
+        for page in &pages {
+            assert!(page.chars().count() <= chunk::EMBED_DESCRIPTION_LIMIT);
+            assert!(page.starts_with("```\n"));
+            assert!(page.ends_with("\n```"));
+        }
+
+        let lines: Vec<&str> = pages.iter()
+            .flat_map(|page| page.lines())
+            .filter(|line| *line != "```")
+            .collect();
+        assert_eq!(lines, list);
+    }
+}
@@ -0,0 +1,627 @@
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use reqwest;
+use serde::Serialize;
+use serenity::prelude::Context;
+use typemap::Key;
+use error::{Error, Result};
+use helpers;
+use strings;
+
+const BASE_URL: &'static str = "https://play.rust-lang.org";
+
+// compiling with optimizations and running miri can take a while
+const TIMEOUT_SECS: u64 = 30;
+const RETRIES: u32 = 2;
+
+// doubled after every retry
+const RETRY_BACKOFF_MILLIS: u64 = 500;
+
+const EDITIONS: &'static [&'static str] = &["2015", "2018", "2021", "2024"];
+
+/// How the playground compiles and runs code, set with flags before the code block
+pub struct Options {
+    pub channel: &'static str,
+    pub mode: &'static str,
+    pub crate_type: &'static str,
+    pub tests: bool,
+    pub edition: Option<String>,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            channel: "stable",
+            mode: "debug",
+            crate_type: "bin",
+            tests: false,
+            edition: None,
+            help: false,
+        }
+    }
+}
+
+/// Parses the flags before a code block, `prefix` is used in the errors to point at the help
+pub fn parse_options(flags: &str, prefix: &str) -> Result<Options> {
+    let mut options = Options::default();
+    let mut flags = flags.split_whitespace();
+
+    while let Some(flag) = flags.next() {
+        match flag {
+            "--stable" => options.channel = "stable",
+            "--beta" => options.channel = "beta",
+            "--nightly" => options.channel = "nightly",
+            "--debug" => options.mode = "debug",
+            "--release" => options.mode = "release",
+            "--test" => options.tests = true,
+            "--lib" => options.crate_type = "lib",
+            "--help" | "-h" => options.help = true,
+            "--edition" => {
+                let edition = flags.next().unwrap_or("");
+
+                if !EDITIONS.contains(&edition) {
+                    return Err(Error::invalid_input(helpers::get_error_f(strings::Error::PlayInvalidEdition, &[
+                        ("edition", &edition),
+                        ("editions", &EDITIONS.join(", ")),
+                    ])));
+                }
+
+                options.edition = Some(edition.to_string());
+            },
+            other => {
+                return Err(Error::invalid_input(helpers::get_error_f(strings::Error::PlayUnknownFlag,
+                                                                     &[("flag", &other), ("prefix", &prefix)])));
+            },
+        }
+    }
+
+    Ok(options)
+}
+
+/// Gets the code in a code block, without the fences and language, leaving
+/// backticks inside the code as they are
+pub fn extract_code(block: &str) -> Option<&str> {
+    let block = block.trim();
+
+    if block.len() < 6 || !block.starts_with("```") || !block.ends_with("```") {
+        return None;
+    }
+
+    let code = &block[3..block.len() - 3];
+
+    // the language is the rest of the first line, if it's a single word
+    match code.find('\n') {
+        Some(i) if code[..i].trim_right().chars().all(|c| c.is_alphanumeric()) => Some(&code[i + 1..]),
+        _ => Some(code),
+    }
+}
+
+/// Wraps code without a main function so the value of the last expression is
+/// printed, statements and items also work inside the block
+pub fn wrap_code(code: &str, options: &Options) -> String {
+    if options.crate_type != "bin" || options.tests || code.contains("fn main") {
+        return code.to_string();
+    }
+
+    format!("fn main() {{\n    println!(\"{{:?}}\", {{\n{}\n    }});\n}}", code)
+}
+
+/// What `compile` outputs instead of a binary
+#[derive(Clone, Copy)]
+pub enum Target {
+    Assembly,
+    Mir,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Request<'a> {
+    channel: &'a str,
+    mode: &'a str,
+    crate_type: &'a str,
+    tests: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edition: Option<&'a str>,
+    code: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    assembly_flavor: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    demangle_assembly: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    process_assembly: Option<&'a str>,
+}
+
+impl<'a> Request<'a> {
+    fn new(code: &'a str, options: &'a Options) -> Request<'a> {
+        Request {
+            channel: options.channel,
+            mode: options.mode,
+            crate_type: options.crate_type,
+            tests: options.tests,
+            edition: options.edition.as_ref().map(|edition| edition.as_str()),
+            code: code,
+            target: None,
+            assembly_flavor: None,
+            demangle_assembly: None,
+            process_assembly: None,
+        }
+    }
+}
+
+/// Output of any of the playground endpoints
+#[derive(Deserialize)]
+pub struct Output {
+    #[serde(default)]
+    pub success: bool,
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+    /// Formatted code, assembly or MIR for the endpoints that return code
+    #[serde(default)]
+    pub code: String,
+}
+
+/// Where the playground is and how long to wait for it, the `[playground]` section of the config
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    #[serde(rename = "url")]
+    pub base_url: String,
+    /// Seconds to wait for a single request
+    pub timeout: u64,
+    /// Times a request is retried after connection errors, server errors or rate limits
+    pub retries: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            base_url: BASE_URL.to_string(),
+            timeout: TIMEOUT_SECS,
+            retries: RETRIES,
+        }
+    }
+}
+
+/// Client for the Rust playground's API
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl Key for Client {
+    type Value = Arc<Client>;
+}
+
+impl Client {
+    pub fn new(settings: Settings) -> Result<Client> {
+        let url = try!(reqwest::Url::parse(&settings.base_url)
+            .map_err(|why| Error::invalid_input(format!("invalid PLAYGROUND_URL {}: {}", settings.base_url, why))));
+
+        match url.scheme() {
+            "https" => {},
+            "http" => warn!("The playground at {} isn't using HTTPS", settings.base_url),
+            scheme => return Err(Error::invalid_input(format!("unsupported PLAYGROUND_URL scheme {}", scheme))),
+        }
+
+        let http = try!(reqwest::Client::builder()
+            .timeout(Duration::from_secs(settings.timeout))
+            .build());
+
+        info!("Using the playground at {} (timeout: {}s, retries: {})",
+              settings.base_url, settings.timeout, settings.retries);
+
+        Ok(Client {
+            http: http,
+            base_url: settings.base_url.trim_right_matches('/').to_string(),
+            timeout: Duration::from_secs(settings.timeout),
+            retries: settings.retries,
+        })
+    }
+
+    // retries connection errors, server errors and rate limits with an increasing
+    // delay, other errors are returned right away.  Timeouts aren't retried since
+    // the code would most likely time out again, and no retry starts after the
+    // timeout of a single request has passed, so a request takes at most about
+    // twice the timeout
+    fn post<T: Serialize>(&self, endpoint: &str, body: &T) -> Result<Output> {
+        let url = format!("{}/{}", self.base_url, endpoint);
+        let start = Instant::now();
+        let mut attempt = 0;
+
+        loop {
+            let result = self.http.post(url.as_str()).json(body).send();
+
+            let retry = match result {
+                Ok(ref res) => res.status().is_server_error() || res.status().as_u16() == 429,
+                Err(ref why) => is_connection_error(why),
+            };
+
+            let backoff = RETRY_BACKOFF_MILLIS * 2u64.pow(attempt);
+
+            if retry && attempt < self.retries && start.elapsed() + Duration::from_millis(backoff) < self.timeout {
+                attempt += 1;
+
+                warn!("Playground request to {} failed, retrying in {}ms ({}/{})", url, backoff, attempt, self.retries);
+                thread::sleep(Duration::from_millis(backoff));
+
+                continue;
+            }
+
+            let mut res = try!(try!(result).error_for_status());
+            return Ok(try!(res.json()));
+        }
+    }
+
+    /// Compiles and runs code
+    pub fn execute(&self, code: &str, options: &Options) -> Result<Output> {
+        self.post("execute", &Request::new(code, options))
+    }
+
+    /// Compiles code to assembly or MIR, returned in `Output::code`
+    pub fn compile(&self, code: &str, options: &Options, target: Target) -> Result<Output> {
+        let mut request = Request::new(code, options);
+
+        match target {
+            Target::Assembly => {
+                request.target = Some("asm");
+                request.assembly_flavor = Some("intel");
+                request.demangle_assembly = Some("demangle");
+                request.process_assembly = Some("filter");
+            },
+            Target::Mir => request.target = Some("mir"),
+        }
+
+        self.post("compile", &request)
+    }
+
+    /// Runs clippy, the lints are in `Output::stderr`
+    pub fn clippy(&self, code: &str, options: &Options) -> Result<Output> {
+        self.post("clippy", &Request::new(code, options))
+    }
+
+    /// Formats code with rustfmt, returned in `Output::code`
+    pub fn format(&self, code: &str, options: &Options) -> Result<Output> {
+        self.post("format", &Request::new(code, options))
+    }
+
+    /// Runs code in miri to check for undefined behaviour
+    pub fn miri(&self, code: &str, options: &Options) -> Result<Output> {
+        self.post("miri", &Request::new(code, options))
+    }
+}
+
+// whether the request failed before getting a response, without timing out
+fn is_connection_error(why: &reqwest::Error) -> bool {
+    let io_kind = why.get_ref()
+        .and_then(|why| why.downcast_ref::<io::Error>())
+        .map(|why| why.kind());
+
+    match io_kind {
+        Some(io::ErrorKind::TimedOut) => false,
+        Some(_) => true,
+        None => why.is_http(),
+    }
+}
+
+/// Gets the playground client without holding the lock on the context data
+pub fn get(ctx: &Context) -> Arc<Client> {
+    let data = ctx.data.lock();
+    data.get::<Client>().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use super::*;
+
+    // the code as the playground receives it
+    fn sent_code(code: &str) -> String {
+        let options = Options::default();
+        let json = serde_json::to_string(&Request::new(code, &options)).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        value["code"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn extract_code_strips_the_fences_and_language() {
+        assert_eq!(extract_code("```rust\nfn main() {}\n```"), Some("fn main() {}\n"));
+        assert_eq!(extract_code("```\n1 + 1```"), Some("1 + 1"));
+        assert_eq!(extract_code("  ```rs\nx\n```  "), Some("x\n"));
+        // not a language, so the first line is code
+        assert_eq!(extract_code("```let x = 1;\nx```"), Some("let x = 1;\nx"));
+    }
+
+    #[test]
+    fn extract_code_needs_a_code_block() {
+        assert_eq!(extract_code("fn main() {}"), None);
+        assert_eq!(extract_code("`1 + 1`"), None);
+        assert_eq!(extract_code("```1 + 1"), None);
+        assert_eq!(extract_code("`````"), None);
+    }
+
+    #[test]
+    fn extract_code_handles_windows_line_endings() {
+        assert_eq!(extract_code("```rust\r\nlet x = 1;\r\n```"), Some("let x = 1;\r\n"));
+    }
+
+    #[test]
+    fn extract_code_keeps_escapes_and_backticks() {
+        let code = "let s = \"a\\\\b\\t\\u{1F980}\\n\";\nlet r = r#\"\\d+ \"quoted\" ```\"#;\nlet c = '`';\n";
+        let block = format!("```rust\n{}```", code);
+
+        assert_eq!(extract_code(&block), Some(code));
+        assert_eq!(extract_code("```\nlet s = \"```\";\n```"), Some("let s = \"```\";\n"));
+    }
+
+    #[test]
+    fn requests_send_the_code_unchanged() {
+        for code in &["let s = \"C:\\\\path\\\\\";",
+                      "let s = \"line\\r\\n\";\r\nlet t = '\\t';",
+                      "fn main() {\n\tprintln!(\"tab\");\n}",
+                      "let crab = \"\\u{1F980} 🦀 é\";",
+                      "let raw = r##\"\\d+ \"# ```\"##;",
+                      "// ``` `nested` ```\nlet tick = '`';",
+                      "\u{0}\u{7f}\u{2028}"] {
+            assert_eq!(sent_code(code), *code);
+        }
+    }
+
+    #[test]
+    fn requests_include_the_options() {
+        let options = parse_options("--nightly --release --edition 2021 --lib --test", "~").unwrap();
+        let value = serde_json::to_value(&Request::new("", &options)).unwrap();
+
+        assert_eq!(value["channel"], "nightly");
+        assert_eq!(value["mode"], "release");
+        assert_eq!(value["edition"], "2021");
+        assert_eq!(value["crateType"], "lib");
+        assert_eq!(value["tests"], true);
+        assert!(value.get("target").is_none());
+
+        let value = serde_json::to_value(&Request::new("", &Options::default())).unwrap();
+        assert!(value.get("edition").is_none());
+    }
+
+    #[test]
+    fn parse_options_rejects_unknown_flags() {
+        assert!(parse_options("--edition 2000", "~").is_err());
+        assert!(parse_options("--edition", "~").is_err());
+        assert!(parse_options("--fast", "~").is_err());
+        assert!(parse_options("", "~").unwrap().edition.is_none());
+    }
+
+    #[test]
+    fn wrap_code_prints_the_last_expression() {
+        let options = Options::default();
+
+        assert_eq!(wrap_code("fn main() {}", &options), "fn main() {}");
+        assert!(wrap_code("1 + 1", &options).contains("println!(\"{:?}\", {\n1 + 1\n    });"));
+
+        let lib = parse_options("--lib", "~").unwrap();
+        assert_eq!(wrap_code("pub fn f() {}", &lib), "pub fn f() {}");
+    }
+
+    // a local stand-in for the playground answering each connection with the
+    // next response, recording the endpoint and body of each request
+    mod mock {
+        use std::io::prelude::*;
+        use std::io::BufReader;
+        use std::net::{TcpListener, TcpStream};
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::Duration;
+
+        pub struct Response {
+            pub status: u16,
+            pub body: &'static str,
+            pub delay_millis: u64,
+        }
+
+        pub fn ok(body: &'static str) -> Response {
+            Response { status: 200, body: body, delay_millis: 0 }
+        }
+
+        pub fn status(status: u16) -> Response {
+            Response { status: status, body: "{}", delay_millis: 0 }
+        }
+
+        pub struct Server {
+            pub url: String,
+            pub requests: Arc<Mutex<Vec<(String, String)>>>,
+        }
+
+        fn respond(stream: TcpStream, response: Response, recorded: Arc<Mutex<Vec<(String, String)>>>) {
+            let mut reader = BufReader::new(stream);
+            let mut path = String::new();
+            let mut length = 0;
+            let mut line = String::new();
+
+            reader.read_line(&mut line).unwrap();
+            path.push_str(line.split_whitespace().nth(1).unwrap_or(""));
+
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+
+                if line.trim().is_empty() {
+                    break;
+                }
+
+                let lower = line.to_lowercase();
+                if lower.starts_with("content-length:") {
+                    length = lower["content-length:".len()..].trim().parse().unwrap();
+                }
+            }
+
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            recorded.lock().unwrap().push((path, String::from_utf8(body).unwrap()));
+
+            thread::sleep(Duration::from_millis(response.delay_millis));
+
+            let mut stream = reader.into_inner();
+            let _ = write!(stream, "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\n\
+                                   Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                           response.status, response.body.len(), response.body);
+        }
+
+        pub fn serve(responses: Vec<Response>) -> Server {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let recorded = requests.clone();
+
+            thread::spawn(move || {
+                for response in responses {
+                    let stream = match listener.accept() {
+                        Ok((stream, _)) => stream,
+                        Err(_) => return,
+                    };
+
+                    // slow responses don't hold up retries on new connections
+                    let recorded = recorded.clone();
+                    thread::spawn(move || respond(stream, response, recorded));
+                }
+            });
+
+            Server {
+                url: url,
+                requests: requests,
+            }
+        }
+    }
+
+    fn client(url: &str, timeout: u64, retries: u32) -> Client {
+        Client::new(Settings {
+            base_url: url.to_string(),
+            timeout: timeout,
+            retries: retries,
+        }).unwrap()
+    }
+
+    fn is_http_error<T>(result: Result<T>) -> bool {
+        match result {
+            Err(Error::Http(_)) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn requests_reach_the_endpoints() {
+        let server = mock::serve(vec![
+            mock::ok(r#"{"success": true, "stdout": "2\n", "stderr": ""}"#),
+            mock::ok(r#"{"success": true, "code": "fn main() {}"}"#),
+            mock::ok(r#"{"success": true, "code": "main:"}"#),
+        ]);
+        let client = client(&format!("{}/", server.url), 5, 0);
+        let options = Options::default();
+
+        let output = client.execute("1 + 1", &options).unwrap();
+        assert!(output.success);
+        assert_eq!(output.stdout, "2\n");
+
+        assert_eq!(client.format("fn main(){}", &options).unwrap().code, "fn main() {}");
+        assert_eq!(client.compile("", &options, Target::Assembly).unwrap().code, "main:");
+
+        let requests = server.requests.lock().unwrap();
+        let paths: Vec<&str> = requests.iter().map(|&(ref path, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["/execute", "/format", "/compile"]);
+
+        let body: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+        assert_eq!(body["code"], "1 + 1");
+        let body: serde_json::Value = serde_json::from_str(&requests[2].1).unwrap();
+        assert_eq!(body["target"], "asm");
+    }
+
+    #[test]
+    fn server_errors_and_rate_limits_are_retried() {
+        let server = mock::serve(vec![
+            mock::status(503),
+            mock::status(429),
+            mock::ok(r#"{"success": true, "stdout": "retried"}"#),
+        ]);
+
+        let output = client(&server.url, 5, 2).execute("", &Options::default()).unwrap();
+        assert_eq!(output.stdout, "retried");
+        assert_eq!(server.requests.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn retries_run_out() {
+        let server = mock::serve(vec![mock::status(500), mock::status(502)]);
+
+        assert!(is_http_error(client(&server.url, 5, 1).execute("", &Options::default())));
+        assert_eq!(server.requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        let server = mock::serve(vec![mock::status(400), mock::ok("{}")]);
+
+        assert!(is_http_error(client(&server.url, 5, 2).execute("", &Options::default())));
+        assert_eq!(server.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn invalid_responses_are_errors() {
+        let server = mock::serve(vec![mock::ok("not json")]);
+
+        assert!(is_http_error(client(&server.url, 5, 2).execute("", &Options::default())));
+        assert_eq!(server.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn timeouts_are_not_retried() {
+        let slow = mock::Response { status: 200, body: "{}", delay_millis: 2500 };
+        let server = mock::serve(vec![slow, mock::ok(r#"{"stdout": "fast"}"#)]);
+
+        assert!(is_http_error(client(&server.url, 1, 2).execute("", &Options::default())));
+        assert_eq!(server.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn retries_stop_after_the_timeout() {
+        let server = mock::serve((0..6).map(|_| mock::status(503)).collect());
+
+        // the second retry would start 1.5s in, after the 1s timeout
+        assert!(is_http_error(client(&server.url, 1, 5).execute("", &Options::default())));
+        assert_eq!(server.requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn connection_errors_are_retried_then_returned() {
+        // nothing listens on the port once the listener is dropped
+        let url = {
+            let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        assert!(is_http_error(client(&url, 1, 1).execute("", &Options::default())));
+    }
+
+    #[test]
+    fn http_errors_are_shown_to_the_user() {
+        let server = mock::serve(vec![mock::status(400)]);
+        let why = client(&server.url, 5, 0).execute("", &Options::default()).err().unwrap();
+
+        assert!(why.to_string().starts_with("HTTP error"));
+    }
+
+    #[test]
+    fn invalid_urls_are_rejected() {
+        for url in &["", "play.rust-lang.org", "ftp://play.rust-lang.org"] {
+            let settings = Settings { base_url: url.to_string(), ..Settings::default() };
+            assert!(Client::new(settings).is_err(), "{} was accepted", url);
+        }
+    }
+}
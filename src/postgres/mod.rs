@@ -0,0 +1,383 @@
+use chrono::prelude::*;
+use r2d2_postgres::PostgresConnectionManager;
+use r2d2_postgres::postgres::{Client, Config, GenericClient, NoTls, Row};
+use r2d2_postgres::r2d2::{Pool, PooledConnection};
+use error::{Error, Result};
+use store;
+use store::{Actions, CommandStore, Creator, CustomCommand, Step, TriggerKind};
+
+// applied in order, the number of applied migrations is kept in schema_migrations
+const MIGRATIONS: &'static [&'static str] = &[
+    include_str!("../../migrations/postgres/0001_create_tables.sql"),
+    include_str!("../../migrations/postgres/0002_add_triggers.sql"),
+    include_str!("../../migrations/postgres/0003_add_actions.sql"),
+    include_str!("../../migrations/postgres/0004_add_scripts.sql"),
+    include_str!("../../migrations/postgres/0005_add_guild_languages.sql"),
+    include_str!("../../migrations/postgres/0006_add_trigger_guilds.sql"),
+];
+
+// arbitrary key for the advisory lock held while migrating, so that
+// instances starting at the same time don't apply migrations twice
+const MIGRATION_LOCK: i64 = 0x636f_6d6d_616e_6473;
+
+// columns selected for a CustomCommand, in the order read by `from_row`
+const COLUMNS: &'static str = "name, url, owner, stat, created, last_used, edited_by, edited, \
+                                trigger_kind, pattern, actions, script, trigger_guild";
+
+fn from_row(row: &Row) -> CustomCommand {
+    let stat: i32 = row.get(3);
+    let created: i64 = row.get(4);
+    let trigger: String = row.get(8);
+
+    CustomCommand {
+        name: row.get(0),
+        url: row.get(1),
+        owner: row.get(2),
+        stat: stat as u32,
+        created: created as u32,
+        last_used: row.get(5),
+        edited_by: row.get(6),
+        edited: row.get(7),
+        trigger: TriggerKind::parse(&trigger).unwrap_or(TriggerKind::Prefix),
+        pattern: row.get(9),
+        trigger_guild: row.get::<_, Option<i64>>(12).map(|id| id as u64),
+        actions: Actions::from_json(row.get(10)),
+        script: store::script_from_json(row.get(11))
+    }
+}
+
+fn migrate(conn: &mut Client) -> Result<()> {
+    try!(conn.execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK]));
+
+    let result = apply_migrations(conn);
+
+    try!(conn.execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK]));
+
+    result
+}
+
+fn apply_migrations(conn: &mut Client) -> Result<()> {
+    try!(conn.batch_execute("CREATE TABLE IF NOT EXISTS schema_migrations (
+                             version         INTEGER PRIMARY KEY
+                             )"));
+
+    let row = try!(conn.query_one("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", &[]));
+    let applied: i32 = row.get(0);
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(applied as usize) {
+        let version = (i + 1) as i32;
+        info!("Applying database migration {}", version);
+
+        let mut trans = try!(conn.transaction());
+        try!(trans.batch_execute(migration));
+        try!(trans.execute("INSERT INTO schema_migrations (version) VALUES ($1)", &[&version]));
+        try!(trans.commit());
+    }
+
+    Ok(())
+}
+
+/// Connects to the database at `url`, applying any pending migrations
+pub fn connect(url: &str) -> Result<PgStore> {
+    let config: Config = try!(url.parse());
+    let manager = PostgresConnectionManager::new(config, NoTls);
+    let pool = try!(Pool::new(manager));
+
+    try!(migrate(&mut *try!(pool.get())));
+
+    Ok(PgStore { pool: pool })
+}
+
+/// Command store backed by PostgreSQL, safe to share between several bot instances
+pub struct PgStore {
+    pool: Pool<PostgresConnectionManager<NoTls>>
+}
+
+impl PgStore {
+    fn conn(&self) -> Result<PooledConnection<PostgresConnectionManager<NoTls>>> {
+        Ok(try!(self.pool.get()))
+    }
+
+    fn query_commands(&self, query: &str, search: Option<&str>) -> Result<Vec<CustomCommand>> {
+        let mut conn = try!(self.conn());
+        let sql = format!("SELECT {} FROM commands {}", COLUMNS, query);
+
+        let rows = match search {
+            Some(search) => try!(conn.query(sql.as_str(), &[&search])),
+            None => try!(conn.query(sql.as_str(), &[])),
+        };
+
+        Ok(rows.iter().map(from_row).collect())
+    }
+}
+
+fn exists<C: GenericClient>(conn: &mut C, name: &str) -> Result<bool> {
+    let rows = try!(conn.query("SELECT 1 FROM commands WHERE name = $1", &[&name]));
+    Ok(!rows.is_empty())
+}
+
+impl CommandStore for PgStore {
+    fn is_command(&self, name: &str) -> Result<bool> {
+        let mut conn = try!(self.conn());
+        exists(&mut *conn, name)
+    }
+
+    fn increment(&self, command: &CustomCommand) -> Result<()> {
+        let mut conn = try!(self.conn());
+        let current_time = Utc::now().timestamp();
+
+        try!(conn.execute("UPDATE commands SET stat = stat + 1, last_used = $1 WHERE name = $2",
+                          &[&current_time, &command.name]));
+
+        Ok(())
+    }
+
+    fn all(&self) -> Result<Vec<CustomCommand>> {
+        self.query_commands("ORDER BY LOWER(name)", None)
+    }
+
+    fn top(&self) -> Result<Vec<CustomCommand>> {
+        self.query_commands("ORDER BY stat DESC", None)
+    }
+
+    fn creators(&self) -> Result<Vec<Creator>> {
+        let mut conn = try!(self.conn());
+        let rows = try!(conn.query("SELECT owner, COUNT(*), COALESCE(SUM(stat), 0) AS uses FROM commands \
+                                    GROUP BY owner ORDER BY COUNT(*) DESC, uses DESC", &[]));
+
+        let creators = rows.iter().map(|row| {
+            let commands: i64 = row.get(1);
+            let uses: i64 = row.get(2);
+
+            Creator {
+                owner: row.get(0),
+                commands: commands as u32,
+                uses: uses as u32
+            }
+        }).collect();
+
+        Ok(creators)
+    }
+
+    fn by_owner(&self, owner: u64) -> Result<Vec<CustomCommand>> {
+        let mut conn = try!(self.conn());
+        let owner = owner as i64;
+        let sql = format!("SELECT {} FROM commands WHERE owner = $1 ORDER BY stat DESC", COLUMNS);
+        let rows = try!(conn.query(sql.as_str(), &[&owner]));
+
+        Ok(rows.iter().map(from_row).collect())
+    }
+
+    fn get(&self, name: &str) -> Result<CustomCommand> {
+        let mut conn = try!(self.conn());
+        let sql = format!("SELECT {} FROM commands WHERE name = $1", COLUMNS);
+        let rows = try!(conn.query(sql.as_str(), &[&name]));
+
+        match rows.iter().next() {
+            Some(row) => Ok(from_row(row)),
+            None => Err(Error::NotFound(name.to_string())),
+        }
+    }
+
+    fn rank(&self, command: &CustomCommand) -> Result<u32> {
+        let mut conn = try!(self.conn());
+        let stat = command.stat as i32;
+        let row = try!(conn.query_one("SELECT COUNT(*) FROM commands WHERE stat > $1", &[&stat]));
+        let higher: i64 = row.get(0);
+
+        Ok(higher as u32 + 1)
+    }
+
+    fn search(&self, search: &str) -> Result<Vec<CustomCommand>> {
+        let search_loose = format!("%{}%", search);
+        self.query_commands("WHERE name ILIKE $1 ORDER BY LOWER(name)", Some(&search_loose))
+    }
+
+    fn add(&self, name: &str, url: &str, owner: u64) -> Result<()> {
+        let mut conn = try!(self.conn());
+        let current_time = Utc::now().timestamp();
+        let owner = owner as i64;
+
+        // the unique name makes this safe against other instances adding the same command
+        let inserted = try!(conn.execute("INSERT INTO commands (name, url, owner, stat, created) \
+                                          VALUES ($1, $2, $3, 0, $4) ON CONFLICT (name) DO NOTHING",
+                                         &[&name, &url, &owner, &current_time]));
+
+        if inserted == 0 {
+            return Err(Error::AlreadyExists(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        let mut conn = try!(self.conn());
+
+        if try!(conn.execute("DELETE FROM commands WHERE name = $1", &[&name])) == 0 {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn delete_all(&self) -> Result<()> {
+        let mut conn = try!(self.conn());
+        try!(conn.execute("DELETE FROM commands", &[]));
+
+        Ok(())
+    }
+
+    fn edit(&self, name: &str, new_name: &str, new_url: &str, editor: u64) -> Result<()> {
+        let mut conn = try!(self.conn());
+        let mut trans = try!(conn.transaction());
+
+        if !try!(exists(&mut trans, name)) {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        // keeping the same name only updates the response
+        if new_name != name && try!(exists(&mut trans, new_name)) {
+            return Err(Error::AlreadyExists(new_name.to_string()));
+        }
+
+        let current_time = Utc::now().timestamp();
+        let editor = editor as i64;
+
+        try!(trans.execute("UPDATE commands SET name = $1, url = $2, edited_by = $3, edited = $4 \
+                            WHERE name = $5", &[&new_name, &new_url, &editor, &current_time, &name]));
+        try!(trans.commit());
+
+        Ok(())
+    }
+
+    fn set_trigger(&self, name: &str, trigger: TriggerKind, pattern: Option<&str>, guild: Option<u64>) -> Result<()> {
+        let mut conn = try!(self.conn());
+        let guild = guild.map(|id| id as i64);
+
+        if try!(conn.execute("UPDATE commands SET trigger_kind = $1, pattern = $2, trigger_guild = $3 WHERE name = $4",
+                             &[&trigger.as_str(), &pattern, &guild, &name])) == 0 {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn set_actions(&self, name: &str, actions: &Actions) -> Result<()> {
+        let mut conn = try!(self.conn());
+
+        if try!(conn.execute("UPDATE commands SET actions = $1 WHERE name = $2",
+                             &[&actions.to_json(), &name])) == 0 {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn set_script(&self, name: &str, script: Option<&[Step]>) -> Result<()> {
+        let mut conn = try!(self.conn());
+
+        if try!(conn.execute("UPDATE commands SET script = $1 WHERE name = $2",
+                             &[&store::script_to_json(script), &name])) == 0 {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn disabled_channels(&self) -> Result<Vec<u64>> {
+        let mut conn = try!(self.conn());
+        let rows = try!(conn.query("SELECT id FROM disabled_trigger_channels", &[]));
+
+        Ok(rows.iter().map(|row| {
+            let id: i64 = row.get(0);
+            id as u64
+        }).collect())
+    }
+
+    fn set_channel_triggers(&self, channel: u64, enabled: bool) -> Result<()> {
+        let mut conn = try!(self.conn());
+        let channel = channel as i64;
+
+        if enabled {
+            try!(conn.execute("DELETE FROM disabled_trigger_channels WHERE id = $1", &[&channel]));
+        } else {
+            try!(conn.execute("INSERT INTO disabled_trigger_channels (id) VALUES ($1) ON CONFLICT DO NOTHING",
+                              &[&channel]));
+        }
+
+        Ok(())
+    }
+
+    fn guild_languages(&self) -> Result<Vec<(u64, String)>> {
+        let mut conn = try!(self.conn());
+        let rows = try!(conn.query("SELECT id, language FROM guild_languages", &[]));
+
+        Ok(rows.iter().map(|row| {
+            let id: i64 = row.get(0);
+            (id as u64, row.get(1))
+        }).collect())
+    }
+
+    fn set_guild_language(&self, guild: u64, language: Option<&str>) -> Result<()> {
+        let mut conn = try!(self.conn());
+        let guild = guild as i64;
+
+        match language {
+            Some(language) => {
+                try!(conn.execute("INSERT INTO guild_languages (id, language) VALUES ($1, $2) \
+                                   ON CONFLICT (id) DO UPDATE SET language = EXCLUDED.language",
+                                  &[&guild, &language]));
+            },
+            None => {
+                try!(conn.execute("DELETE FROM guild_languages WHERE id = $1", &[&guild]));
+            },
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use store;
+    use super::*;
+
+    // the tests empty the database, so they only run if DATABASE_URL points at one
+    fn url() -> Option<String> {
+        match env::var("DATABASE_URL") {
+            Ok(ref url) if store::is_postgres_url(url) => Some(url.clone()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn conformance() {
+        let url = match url() {
+            Some(url) => url,
+            None => return,
+        };
+
+        store::tests::conformance(&connect(&url).unwrap());
+    }
+
+    #[test]
+    fn migrations_are_applied_once() {
+        let url = match url() {
+            Some(url) => url,
+            None => return,
+        };
+
+        // connecting again doesn't apply the migrations a second time
+        connect(&url).unwrap();
+        let db = connect(&url).unwrap();
+
+        let mut conn = db.conn().unwrap();
+        let row = conn.query_one("SELECT COUNT(*), MAX(version) FROM schema_migrations", &[]).unwrap();
+        let count: i64 = row.get(0);
+        let latest: i32 = row.get(1);
+
+        assert_eq!(count, MIGRATIONS.len() as i64);
+        assert_eq!(latest, MIGRATIONS.len() as i32);
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serenity::CACHE;
+use serenity::prelude::*;
+use serenity::model::{ChannelId, Message, MessageId};
+use serenity::model::event::MessageUpdateEvent;
+use typemap::Key;
+use commands;
+use dispatch;
+use locale;
+
+// how long after the last run editing a message runs it again
+const WINDOW_SECS: u64 = 10 * 60;
+
+// most messages tracked at once, the oldest are dropped first
+const MAX_TRACKED: usize = 100;
+
+/// The reply to a `~play` message, edited when the message is edited
+pub struct Run {
+    reply: MessageId,
+    last_run: Instant,
+}
+
+impl Run {
+    fn is_expired(&self) -> bool {
+        self.last_run.elapsed() >= Duration::from_secs(WINDOW_SECS)
+    }
+}
+
+pub struct Runs;
+
+impl Key for Runs {
+    type Value = HashMap<MessageId, Run>;
+}
+
+/// Remembers the reply to a `~play` message so editing the message updates it
+pub fn track(ctx: &Context, msg: &Message, reply: MessageId) {
+    let mut data = ctx.data.lock();
+    let runs = data.get_mut::<Runs>().unwrap();
+
+    runs.retain(|_, run| !run.is_expired());
+
+    if runs.len() >= MAX_TRACKED {
+        let oldest = runs.iter().min_by_key(|&(_, run)| run.last_run).map(|(id, _)| *id);
+
+        if let Some(oldest) = oldest {
+            runs.remove(&oldest);
+        }
+    }
+
+    runs.insert(msg.id, Run {
+        reply: reply,
+        last_run: Instant::now(),
+    });
+}
+
+// the reply to a message if it was run recently enough
+fn reply_to(ctx: &Context, id: MessageId) -> Option<MessageId> {
+    let mut data = ctx.data.lock();
+    let runs = data.get_mut::<Runs>().unwrap();
+
+    let expired = match runs.get(&id) {
+        Some(run) if !run.is_expired() => return Some(run.reply),
+        Some(_) => true,
+        None => false,
+    };
+
+    if expired {
+        runs.remove(&id);
+    }
+
+    None
+}
+
+/// Runs the code of a tracked `~play` message again after it's edited
+pub fn handle_update(ctx: &Context, event: &MessageUpdateEvent, prefix: &str) {
+    // updates without content are embeds being added to the message
+    if event.content.is_none() {
+        return;
+    }
+
+    let reply = match reply_to(ctx, event.id) {
+        Some(reply) => reply,
+        None => return,
+    };
+
+    let msg = match event.channel_id.message(event.id) {
+        Ok(msg) => msg,
+        Err(why) => return warn!("Failed to get edited message {}: {:?}", event.id, why),
+    };
+
+    let bot_id = CACHE.read().unwrap().user.id;
+
+    let invocation = match dispatch::parse(&msg.content, prefix, bot_id) {
+        Some(invocation) => invocation,
+        None => return,
+    };
+
+    // the message was edited into something else, leave the old output
+    if invocation.name != "play" {
+        return;
+    }
+
+    info!("Running edited message {} by {} again", msg.id, msg.author.tag());
+    locale::use_for(ctx, &msg);
+
+    match commands::misc::run_play(ctx, &msg, &invocation.rest, Some(reply)) {
+        Ok(Some(reply)) => track(ctx, &msg, reply),
+        Ok(None) => {},
+        Err(why) => {
+            error!("Running edited message {} failed: {:?}", msg.id, why);
+
+            if let Some(reply) = commands::misc::send_reply(&msg, Some(reply), &why.user_message(), None) {
+                track(ctx, &msg, reply);
+            }
+        },
+    }
+}
+
+/// Stops tracking a deleted `~play` message and deletes its reply
+pub fn handle_delete(ctx: &Context, channel: ChannelId, id: MessageId) {
+    let run = {
+        let mut data = ctx.data.lock();
+        data.get_mut::<Runs>().unwrap().remove(&id)
+    };
+
+    if let Some(run) = run {
+        if let Err(why) = channel.delete_message(run.reply) {
+            warn!("Failed to delete the reply to deleted message {}: {:?}", id, why);
+        }
+    }
+}
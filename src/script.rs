@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use serenity::prelude::Context;
+use serenity::model::{ChannelId, Message, UserId};
+use serenity::utils::Colour;
+use typemap::Key;
+use error::{Error, Result};
+use store::{CustomCommand, Step};
+use actions;
+use chunk;
+use helpers;
+use strings;
+use locale;
+
+pub const MAX_STEPS: usize = 20;
+pub const MAX_DELAY_SECS: u64 = 60;
+pub const MAX_TOTAL_DELAY_SECS: u64 = 300;
+
+// scripts running at once in a single channel
+const MAX_RUNNING: usize = 3;
+
+// how often delays check if the script was cancelled
+const POLL_MILLIS: u64 = 250;
+
+/// Checks that a script can be sent and doesn't take too long
+pub fn validate(steps: &[Step]) -> Result<()> {
+    if steps.is_empty() || steps.len() > MAX_STEPS {
+        return Err(Error::invalid_input(helpers::get_error_f(strings::Error::ScriptLength,
+                                                             &[("max", &MAX_STEPS)])));
+    }
+
+    let mut total_delay = 0;
+
+    for step in steps {
+        match *step {
+            Step::Text { ref content } => {
+                if content.is_empty() || content.chars().count() > chunk::MESSAGE_LIMIT {
+                    return Err(Error::invalid_input(
+                        helpers::get_error_f(strings::Error::ScriptTextLength,
+                                             &[("max", &chunk::MESSAGE_LIMIT)])));
+                }
+            },
+            Step::Embed { ref description, .. } => {
+                if description.as_ref().map_or(0, |d| d.chars().count()) > chunk::EMBED_DESCRIPTION_LIMIT {
+                    return Err(Error::invalid_input(
+                        helpers::get_error_f(strings::Error::ScriptTextLength,
+                                             &[("max", &chunk::EMBED_DESCRIPTION_LIMIT)])));
+                }
+            },
+            Step::Delay { seconds } => {
+                if seconds > MAX_DELAY_SECS {
+                    return Err(Error::invalid_input(
+                        helpers::get_error_f(strings::Error::ScriptDelay, &[("max", &MAX_DELAY_SECS)])));
+                }
+
+                total_delay += seconds;
+            },
+            Step::React { .. } | Step::Typing => {},
+        }
+    }
+
+    if total_delay > MAX_TOTAL_DELAY_SECS {
+        return Err(Error::invalid_input(
+            helpers::get_error_f(strings::Error::ScriptTotalDelay, &[("max", &MAX_TOTAL_DELAY_SECS)])));
+    }
+
+    Ok(())
+}
+
+/// Fills in the placeholders of the text in a script, cutting down text that
+/// arguments made too long to send
+pub fn render<F: Fn(&str) -> String>(steps: &[Step], render: F) -> Vec<Step> {
+    let render_opt = |text: &Option<String>, limit| text.as_ref().map(|text| chunk::truncate(&render(text), limit));
+
+    steps.iter().map(|step| match *step {
+        Step::Text { ref content } => Step::Text { content: chunk::truncate(&render(content), chunk::MESSAGE_LIMIT) },
+        Step::Embed { ref title, ref description, colour, ref image } => Step::Embed {
+            title: render_opt(title, chunk::EMBED_TITLE_LIMIT),
+            description: render_opt(description, chunk::EMBED_DESCRIPTION_LIMIT),
+            colour: colour,
+            image: image.clone()
+        },
+        ref step => step.clone(),
+    }).collect()
+}
+
+struct Running {
+    id: usize,
+    user: UserId,
+    cancelled: Arc<AtomicBool>
+}
+
+/// Scripts that are running, by the channel the command was used in
+pub struct Scripts {
+    next_id: AtomicUsize,
+    running: Mutex<HashMap<ChannelId, Vec<Running>>>
+}
+
+impl Key for Scripts {
+    type Value = Arc<Scripts>;
+}
+
+impl Scripts {
+    pub fn new() -> Scripts {
+        Scripts {
+            next_id: AtomicUsize::new(0),
+            running: Mutex::new(HashMap::new())
+        }
+    }
+
+    // registers a script, None if too many are running in the channel already
+    fn start(&self, channel: ChannelId, user: UserId) -> Option<(usize, Arc<AtomicBool>)> {
+        let mut running = self.running.lock().unwrap();
+        let scripts = running.entry(channel).or_insert_with(Vec::new);
+
+        if scripts.len() >= MAX_RUNNING {
+            return None;
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        scripts.push(Running {
+            id: id,
+            user: user,
+            cancelled: cancelled.clone()
+        });
+
+        Some((id, cancelled))
+    }
+
+    fn finish(&self, channel: ChannelId, id: usize) {
+        let mut running = self.running.lock().unwrap();
+
+        let empty = match running.get_mut(&channel) {
+            Some(scripts) => {
+                scripts.retain(|script| script.id != id);
+                scripts.is_empty()
+            },
+            None => false,
+        };
+
+        if empty {
+            running.remove(&channel);
+        }
+    }
+
+    /// Cancels the scripts running in a channel, only those started by `user` if
+    /// given, returning how many were cancelled
+    pub fn cancel(&self, channel: ChannelId, user: Option<UserId>) -> usize {
+        let running = self.running.lock().unwrap();
+
+        let scripts = match running.get(&channel) {
+            Some(scripts) => scripts,
+            None => return 0,
+        };
+
+        let mut cancelled = 0;
+        for script in scripts.iter().filter(|script| user.map_or(true, |user| script.user == user)) {
+            script.cancelled.store(true, Ordering::SeqCst);
+            cancelled += 1;
+        }
+
+        cancelled
+    }
+}
+
+/// Gets the running scripts without holding the lock on the context data
+pub fn get(ctx: &Context) -> Arc<Scripts> {
+    let data = ctx.data.lock();
+    data.get::<Scripts>().unwrap().clone()
+}
+
+// sleeps in short intervals, returning early if the script is cancelled
+fn delay(seconds: u64, cancelled: &AtomicBool) {
+    let mut waited = 0;
+
+    while waited < seconds * 1000 && !cancelled.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(POLL_MILLIS));
+        waited += POLL_MILLIS;
+    }
+}
+
+fn run_step(msg: &Message, channel: ChannelId, step: &Step, cancelled: &AtomicBool) -> ::serenity::Result<()> {
+    match *step {
+        Step::Text { ref content } => {
+            try!(channel.say(content));
+        },
+        Step::Embed { ref title, ref description, colour, ref image } => {
+            try!(channel.send_message(|m| m.embed(|e| {
+                let mut e = e;
+
+                if let Some(ref title) = *title {
+                    e = e.title(title);
+                }
+
+                if let Some(ref description) = *description {
+                    e = e.description(description);
+                }
+
+                if let Some(colour) = colour {
+                    e = e.colour(Colour::new(colour));
+                }
+
+                if let Some(ref image) = *image {
+                    e = e.image(image);
+                }
+
+                e
+            })));
+        },
+        Step::React { ref emoji } => try!(msg.react(actions::parse_reaction(emoji))),
+        Step::Delay { seconds } => delay(seconds, cancelled),
+        Step::Typing => try!(channel.broadcast_typing()),
+    }
+
+    Ok(())
+}
+
+/// Runs a script in another thread so the shard isn't blocked by delays,
+/// returns false if too many scripts are running in the channel already
+pub fn start(ctx: &Context, msg: &Message, command: &CustomCommand, channel: ChannelId, steps: Vec<Step>) -> bool {
+    let scripts = get(ctx);
+
+    // registered where the command was used, which is where `~cancel` is used
+    let (id, cancelled) = match scripts.start(msg.channel_id, msg.author.id) {
+        Some(running) => running,
+        None => return false,
+    };
+
+    let msg = msg.clone();
+    let command = command.clone();
+    let language = locale::language();
+
+    thread::spawn(move || {
+        locale::set_language(&language);
+
+        for step in &steps {
+            if cancelled.load(Ordering::SeqCst) {
+                break;
+            }
+
+            if let Err(why) = run_step(&msg, channel, step, &cancelled) {
+                actions::failed(&msg, &command, strings::Error::ActionScript, why);
+                break;
+            }
+        }
+
+        scripts.finish(msg.channel_id, id);
+    });
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use serde_json;
+    use super::*;
+
+    fn text(content: &str) -> Step {
+        Step::Text { content: content.to_string() }
+    }
+
+    fn delay_step(seconds: u64) -> Step {
+        Step::Delay { seconds: seconds }
+    }
+
+    #[test]
+    fn steps_are_parsed() {
+        let steps: Vec<Step> = serde_json::from_str(r#"[
+            {"type": "text", "content": "hi {user}"},
+            {"type": "embed", "title": "Title", "colour": 16711680},
+            {"type": "react", "emoji": "🦀"},
+            {"type": "delay", "seconds": 2},
+            {"type": "typing"}
+        ]"#).unwrap();
+
+        assert_eq!(steps, vec![
+            text("hi {user}"),
+            Step::Embed { title: Some("Title".to_string()), description: None, colour: Some(0xFF0000), image: None },
+            Step::React { emoji: "🦀".to_string() },
+            delay_step(2),
+            Step::Typing,
+        ]);
+        validate(&steps).unwrap();
+
+        assert!(serde_json::from_str::<Vec<Step>>(r#"[{"type": "explode"}]"#).is_err());
+        assert!(serde_json::from_str::<Vec<Step>>(r#"[{"type": "text"}]"#).is_err());
+    }
+
+    #[test]
+    fn step_count_is_limited() {
+        assert!(validate(&[]).is_err());
+        assert!(validate(&vec![text("a"); MAX_STEPS]).is_ok());
+        assert!(validate(&vec![text("a"); MAX_STEPS + 1]).is_err());
+    }
+
+    #[test]
+    fn text_length_is_limited() {
+        assert!(validate(&[text("")]).is_err());
+        assert!(validate(&[text(&"é".repeat(chunk::MESSAGE_LIMIT))]).is_ok());
+        assert!(validate(&[text(&"a".repeat(chunk::MESSAGE_LIMIT + 1))]).is_err());
+
+        let embed = |len| Step::Embed { title: None, description: Some("a".repeat(len)), colour: None, image: None };
+        assert!(validate(&[embed(chunk::EMBED_DESCRIPTION_LIMIT)]).is_ok());
+        assert!(validate(&[embed(chunk::EMBED_DESCRIPTION_LIMIT + 1)]).is_err());
+    }
+
+    #[test]
+    fn delays_are_limited() {
+        assert!(validate(&[delay_step(MAX_DELAY_SECS)]).is_ok());
+        assert!(validate(&[delay_step(MAX_DELAY_SECS + 1)]).is_err());
+
+        let steps = vec![delay_step(MAX_DELAY_SECS); (MAX_TOTAL_DELAY_SECS / MAX_DELAY_SECS) as usize];
+        assert!(validate(&steps).is_ok());
+
+        let mut steps = steps;
+        steps.push(delay_step(1));
+        assert!(validate(&steps).is_err());
+    }
+
+    #[test]
+    fn rendered_text_fits_in_a_message() {
+        let steps = vec![
+            text(&format!("{}{{args}}", "a".repeat(chunk::MESSAGE_LIMIT - 10))),
+            Step::Embed { title: Some("{args}".to_string()), description: Some("{args}".to_string()),
+                          colour: None, image: Some("{args}".to_string()) },
+            delay_step(1),
+        ];
+        let args = "b".repeat(5000);
+
+        let rendered = render(&steps, |text| text.replace("{args}", &args));
+
+        match rendered[0] {
+            Step::Text { ref content } => {
+                assert_eq!(content.chars().count(), chunk::MESSAGE_LIMIT);
+                assert!(content.ends_with("b…"));
+            },
+            ref other => panic!("expected text, got {:?}", other),
+        }
+
+        match rendered[1] {
+            Step::Embed { ref title, ref description, ref image, .. } => {
+                assert_eq!(title.as_ref().unwrap().chars().count(), chunk::EMBED_TITLE_LIMIT);
+                assert_eq!(description.as_ref().unwrap().chars().count(), chunk::EMBED_DESCRIPTION_LIMIT);
+                // images are urls and aren't rendered
+                assert_eq!(image.as_ref().unwrap(), "{args}");
+            },
+            ref other => panic!("expected an embed, got {:?}", other),
+        }
+
+        assert_eq!(rendered[2], delay_step(1));
+    }
+
+    #[test]
+    fn running_scripts_are_limited_per_channel() {
+        let scripts = Scripts::new();
+        let ids: Vec<usize> = (0..MAX_RUNNING)
+            .map(|_| scripts.start(ChannelId(1), UserId(1)).unwrap().0)
+            .collect();
+
+        assert!(scripts.start(ChannelId(1), UserId(2)).is_none());
+        assert!(scripts.start(ChannelId(2), UserId(1)).is_some());
+
+        scripts.finish(ChannelId(1), ids[0]);
+        assert!(scripts.start(ChannelId(1), UserId(2)).is_some());
+    }
+
+    #[test]
+    fn cancelling_only_affects_the_users_scripts() {
+        let scripts = Scripts::new();
+        let (_, first) = scripts.start(ChannelId(1), UserId(1)).unwrap();
+        let (_, second) = scripts.start(ChannelId(1), UserId(2)).unwrap();
+        let (_, elsewhere) = scripts.start(ChannelId(2), UserId(1)).unwrap();
+
+        assert_eq!(scripts.cancel(ChannelId(1), Some(UserId(1))), 1);
+        assert!(first.load(Ordering::SeqCst));
+        assert!(!second.load(Ordering::SeqCst));
+        assert!(!elsewhere.load(Ordering::SeqCst));
+
+        // members who can manage the guild cancel everyone's
+        assert_eq!(scripts.cancel(ChannelId(1), None), 2);
+        assert!(second.load(Ordering::SeqCst));
+
+        assert_eq!(scripts.cancel(ChannelId(3), None), 0);
+    }
+
+    #[test]
+    fn cancelled_delays_end_early() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            flag.store(true, Ordering::SeqCst);
+        });
+
+        let start = Instant::now();
+        delay(MAX_DELAY_SECS, &cancelled);
+
+        assert!(start.elapsed() < Duration::from_secs(MAX_DELAY_SECS));
+    }
+}
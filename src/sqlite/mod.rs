@@ -1,155 +1,383 @@
-use std::path::Path;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use chrono::prelude::*;
-use rusqlite::{Connection, Error};
-
-pub struct CustomCommand {
-    pub name: String,
-    pub url: String,
-    pub owner: i64,
-    pub stat: u32,
-    pub created: u32
+use rusqlite;
+use rusqlite::{Connection, DatabaseName, Row};
+use rusqlite::backup::Progress;
+use error::{Error, Result};
+use helpers;
+use strings;
+use store;
+use store::{Actions, CommandStore, Creator, CustomCommand, Step, TriggerKind};
+
+const DB_PATH: &'static str = "database.sqlite3";
+const BUSY_TIMEOUT: u32 = 5000;
+const READERS: usize = 4;
+
+// tables and columns that have to exist in a database for it to be restored
+const REQUIRED_COLUMNS: &'static [(&'static str, &'static [&'static str])] = &[
+    ("commands", &["id", "name", "url", "owner", "stat", "created"]),
+    ("servers", &["id", "prefix"]),
+];
+
+// columns selected for a CustomCommand, in the order read by `from_row`
+const COLUMNS: &'static str = "name, url, owner, stat, created, last_used, edited_by, edited, \
+                                trigger_kind, pattern, actions, script, trigger_guild";
+
+fn from_row(row: &Row) -> CustomCommand {
+    let trigger: String = row.get(8);
+
+    CustomCommand {
+        name: row.get(0),
+        url: row.get(1),
+        owner: row.get(2),
+        stat: row.get(3),
+        created: row.get(4),
+        last_used: row.get(5),
+        edited_by: row.get(6),
+        edited: row.get(7),
+        trigger: TriggerKind::parse(&trigger).unwrap_or(TriggerKind::Prefix),
+        pattern: row.get(9),
+        trigger_guild: row.get::<_, Option<i64>>(12).map(|id| id as u64),
+        actions: Actions::from_json(row.get(10)),
+        script: store::script_from_json(row.get(11))
+    }
 }
 
-impl CustomCommand {
-    pub fn is_owner(&self, id: u64) -> bool {
-        id == self.owner as u64
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = try!(conn.prepare(&format!("PRAGMA table_info({})", table)));
+    let mut rows = try!(stmt.query(&[]));
+
+    let mut columns = Vec::new();
+    while let Some(result_row) = rows.next() {
+        let row = try!(result_row);
+        columns.push(row.get(1));
     }
+
+    Ok(columns)
 }
 
-const DB_PATH: &'static str = "database.sqlite3";
+// adds a column to databases created before it existed
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    if try!(table_columns(conn, table)).iter().any(|c| c == column) {
+        return Ok(());
+    }
+
+    try!(conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), &[]));
+
+    Ok(())
+}
+
+// checks that a file is a database with the tables and columns a backup needs,
+// columns added later are created when restoring
+fn validate(path: &Path) -> Result<()> {
+    if !path.is_file() {
+        return Err(Error::invalid_input(helpers::get_error_f(strings::Error::BackupNotAFile,
+                                                             &[("path", &path.display())])));
+    }
 
-pub fn connect() -> Result<Database, Error> {
-    let conn = try!(Connection::open(Path::new(DB_PATH)));
+    let conn = try!(Connection::open(path));
+    try!(conn.execute_batch("PRAGMA query_only = ON;"));
 
+    let integrity: String = try!(conn.query_row("PRAGMA integrity_check", &[], |row| row.get(0))
+        .map_err(Error::invalid_input));
+
+    if integrity != "ok" {
+        return Err(Error::invalid_input(helpers::get_error_f(strings::Error::BackupIntegrity,
+                                                             &[("error", &integrity)])));
+    }
+
+    for &(table, required) in REQUIRED_COLUMNS {
+        let columns = try!(table_columns(&conn, table));
+
+        for column in required {
+            if !columns.iter().any(|c| c == *column) {
+                let column = format!("{}.{}", table, column);
+                return Err(Error::invalid_input(helpers::get_error_f(strings::Error::BackupMissingColumn,
+                                                                     &[("column", &column)])));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Settings used to open the SQLite database, the `[sqlite]` section of the config
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub path: PathBuf,
+    /// How long to wait for locks held by other connections, in milliseconds
+    pub busy_timeout: u32,
+    /// Number of read-only connections used for lookups, separate from the writer
+    pub readers: usize
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            path: PathBuf::from(DB_PATH),
+            busy_timeout: BUSY_TIMEOUT,
+            readers: READERS
+        }
+    }
+}
+
+pub fn open(settings: &Settings) -> Result<Database> {
+    let conn = try!(Connection::open(&settings.path));
+    try!(configure(&conn, settings.busy_timeout));
+
+    // readers don't block the writer and the writer doesn't block readers
+    let journal_mode: String = try!(conn.query_row("PRAGMA journal_mode = WAL", &[], |row| row.get(0)));
+
+    try!(create_tables(&conn));
+
+    let mut readers = Vec::new();
+    for _ in 0..settings.readers {
+        let reader = try!(Connection::open(&settings.path));
+        try!(configure(&reader, settings.busy_timeout));
+        try!(reader.execute_batch("PRAGMA query_only = ON;"));
+
+        readers.push(Mutex::new(reader));
+    }
+
+    info!("Opened SQLite database at {} (journal mode: {}, busy timeout: {}ms, read connections: {})",
+          settings.path.display(), journal_mode, settings.busy_timeout, readers.len());
+
+    Ok(Database {
+        conn: Mutex::new(conn),
+        readers: readers,
+        next_reader: AtomicUsize::new(0)
+    })
+}
+
+/// Opens a database that only lives as long as the returned value
+#[cfg(test)]
+pub fn open_in_memory() -> Result<Database> {
+    let conn = try!(Connection::open_in_memory());
+    try!(configure(&conn, BUSY_TIMEOUT));
+    try!(create_tables(&conn));
+
+    // separate connections would each get their own database
+    Ok(Database {
+        conn: Mutex::new(conn),
+        readers: Vec::new(),
+        next_reader: AtomicUsize::new(0)
+    })
+}
+
+fn configure(conn: &Connection, busy_timeout: u32) -> Result<()> {
+    try!(conn.execute_batch(&format!("PRAGMA busy_timeout = {};
+                                      PRAGMA foreign_keys = ON;", busy_timeout)));
+
+    Ok(())
+}
+
+// creates and migrates the tables
+fn create_tables(conn: &Connection) -> Result<()> {
     try!(conn.execute("CREATE TABLE IF NOT EXISTS commands (
                       id              INTEGER PRIMARY KEY,
                       name            TEXT NOT NULL,
                       url             TEXT NOT NULL,
                       owner           INTEGER,
                       stat            INTEGER,
-                      created         INTEGER
+                      created         INTEGER,
+                      last_used       INTEGER,
+                      edited_by       INTEGER,
+                      edited          INTEGER,
+                      trigger_kind    TEXT NOT NULL DEFAULT 'prefix',
+                      pattern         TEXT,
+                      actions         TEXT,
+                      script          TEXT,
+                      trigger_guild   INTEGER
                       )", &[]));
 
+    try!(add_column(conn, "commands", "last_used", "INTEGER"));
+    try!(add_column(conn, "commands", "edited_by", "INTEGER"));
+    try!(add_column(conn, "commands", "edited", "INTEGER"));
+    try!(add_column(conn, "commands", "trigger_kind", "TEXT NOT NULL DEFAULT 'prefix'"));
+    try!(add_column(conn, "commands", "pattern", "TEXT"));
+    try!(add_column(conn, "commands", "actions", "TEXT"));
+    try!(add_column(conn, "commands", "script", "TEXT"));
+    try!(add_column(conn, "commands", "trigger_guild", "INTEGER"));
+
     try!(conn.execute("CREATE TABLE IF NOT EXISTS servers (
                       id              INTEGER PRIMARY KEY,
                       prefix          TEXT NOT NULL
                       )", &[]));
-    
-    let db = Database {conn: Mutex::new(conn)};
 
-    Ok(db)
+    try!(conn.execute("CREATE TABLE IF NOT EXISTS disabled_trigger_channels (
+                      id              INTEGER PRIMARY KEY
+                      )", &[]));
+
+    try!(conn.execute("CREATE TABLE IF NOT EXISTS guild_languages (
+                      id              INTEGER PRIMARY KEY,
+                      language        TEXT NOT NULL
+                      )", &[]));
+
+    Ok(())
+}
+
+fn exists(conn: &Connection, name: &str) -> Result<bool> {
+    let mut stmt = try!(conn.prepare_cached("SELECT 1 FROM commands WHERE name = ?"));
+    Ok(try!(stmt.exists(&[&name])))
 }
 
 pub struct Database {
-    conn: Mutex<Connection>
+    // the only connection used for writes
+    conn: Mutex<Connection>,
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize
 }
 
 impl Database {
-    pub fn is_command(&self, name: &String) -> Result<bool, Error> {
-        let conn = &self.conn.lock().unwrap();
-        let mut stmt = try!(conn.prepare_cached("SELECT * FROM commands WHERE name = ?"));
-        stmt.exists(&[name])
+    // a read-only connection, so lookups don't wait behind long writes like imports
+    fn reader(&self) -> MutexGuard<Connection> {
+        if self.readers.is_empty() {
+            return self.conn.lock().unwrap();
+        }
+
+        // prefer an idle connection, otherwise wait for the next one in turn
+        for reader in &self.readers {
+            if let Ok(conn) = reader.try_lock() {
+                return conn;
+            }
+        }
+
+        let i = self.next_reader.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        self.readers[i].lock().unwrap()
+    }
+}
+
+impl CommandStore for Database {
+    fn is_command(&self, name: &str) -> Result<bool> {
+        let conn = &self.reader();
+        exists(conn, name)
     }
 
-    pub fn increment(&self, command: &CustomCommand) -> Result<(), Error> {
+    fn increment(&self, command: &CustomCommand) -> Result<()> {
         let conn = &self.conn.lock().unwrap();
-        let mut stmt = try!(conn.prepare_cached("UPDATE commands SET stat = ?1 WHERE name = ?2"));
+        let mut stmt = try!(conn.prepare_cached("UPDATE commands SET stat = stat + 1, last_used = ?1 WHERE name = ?2"));
 
-        let new_stat = command.stat + 1;
-        try!(stmt.execute(&[&new_stat, &command.name]));
+        let current_time = Utc::now().timestamp();
+        try!(stmt.execute(&[&current_time, &command.name]));
 
         Ok(())
     }
 
-    pub fn all(&self) -> Result<Vec<CustomCommand>, Error> {
-        let conn = &self.conn.lock().unwrap();
-        let mut stmt = try!(conn.prepare_cached("SELECT * FROM commands ORDER BY LOWER(name)"));
+    fn all(&self) -> Result<Vec<CustomCommand>> {
+        let conn = &self.reader();
+        let mut stmt = try!(conn.prepare_cached(&format!("SELECT {} FROM commands ORDER BY LOWER(name)", COLUMNS)));
         let mut rows = try!(stmt.query(&[]));
 
         let mut commands = Vec::new();
         while let Some(result_row) = rows.next() {
             let row = try!(result_row);
 
-            let cmd = CustomCommand {
-                name: row.get(1),
-                url: row.get(2),
-                owner: row.get(3),
-                stat: row.get(4),
-                created: row.get(5)
-            };
-
-            commands.push(cmd);
+            commands.push(from_row(&row));
         }
 
         Ok(commands)
     }
 
-    pub fn top(&self) -> Result<Vec<CustomCommand>, Error> {
-        let conn = &self.conn.lock().unwrap();
-        let mut stmt = try!(conn.prepare_cached("SELECT * FROM commands ORDER BY stat DESC"));
+    fn top(&self) -> Result<Vec<CustomCommand>> {
+        let conn = &self.reader();
+        let mut stmt = try!(conn.prepare_cached(&format!("SELECT {} FROM commands ORDER BY stat DESC", COLUMNS)));
         let mut rows = try!(stmt.query(&[]));
 
         let mut commands = Vec::new();
         while let Some(result_row) = rows.next() {
             let row = try!(result_row);
 
-            let cmd = CustomCommand {
-                name: row.get(1),
-                url: row.get(2),
-                owner: row.get(3),
-                stat: row.get(4),
-                created: row.get(5)
-            };
-
-            commands.push(cmd);
+            commands.push(from_row(&row));
         }
 
         Ok(commands)
     }
 
-    pub fn get(&self, name: &String) -> Result<CustomCommand, Error> {
-        let conn = &self.conn.lock().unwrap();
-        let mut stmt = try!(conn.prepare_cached("SELECT * FROM commands WHERE name = ?"));
-        let row = try!(stmt.query_row(&[name], |row| CustomCommand {
-            name: row.get(1),
-            url: row.get(2),
-            owner: row.get(3),
-            stat: row.get(4),
-            created: row.get(5)
-        }));
+    fn creators(&self) -> Result<Vec<Creator>> {
+        let conn = &self.reader();
+        let mut stmt = try!(conn.prepare_cached("SELECT owner, COUNT(*), COALESCE(SUM(stat), 0) AS uses FROM commands \
+                                                 GROUP BY owner ORDER BY COUNT(*) DESC, uses DESC"));
+        let mut rows = try!(stmt.query(&[]));
+
+        let mut creators = Vec::new();
+        while let Some(result_row) = rows.next() {
+            let row = try!(result_row);
+
+            let commands: i64 = row.get(1);
+            let uses: i64 = row.get(2);
+
+            creators.push(Creator {
+                owner: row.get(0),
+                commands: commands as u32,
+                uses: uses as u32
+            });
+        }
 
-        Ok(row)
+        Ok(creators)
     }
 
+    fn by_owner(&self, owner: u64) -> Result<Vec<CustomCommand>> {
+        let conn = &self.reader();
+        let mut stmt = try!(conn.prepare_cached(&format!("SELECT {} FROM commands WHERE owner = ? \
+                                                          ORDER BY stat DESC", COLUMNS)));
+        let owner = owner as i64;
+        let mut rows = try!(stmt.query(&[&owner]));
 
-    pub fn search(&self, search: &String) -> Result<Vec<CustomCommand>, Error> {
-        let conn = &self.conn.lock().unwrap();
-        let mut stmt = try!(conn.prepare_cached("SELECT * FROM commands WHERE name LIKE ?1 \
-                                                 or name LIKE ?2 ORDER BY LOWER(name)"));
+        let mut commands = Vec::new();
+        while let Some(result_row) = rows.next() {
+            let row = try!(result_row);
+
+            commands.push(from_row(&row));
+        }
+
+        Ok(commands)
+    }
+
+    fn get(&self, name: &str) -> Result<CustomCommand> {
+        let conn = &self.reader();
+        let mut stmt = try!(conn.prepare_cached(&format!("SELECT {} FROM commands WHERE name = ?", COLUMNS)));
+        match stmt.query_row(&[&name], from_row) {
+            Ok(row) => Ok(row),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::NotFound(name.to_string())),
+            Err(why) => Err(why.into()),
+        }
+    }
+
+    fn rank(&self, command: &CustomCommand) -> Result<u32> {
+        let conn = &self.reader();
+        let mut stmt = try!(conn.prepare_cached("SELECT COUNT(*) FROM commands WHERE stat > ?"));
+        let higher: i64 = try!(stmt.query_row(&[&command.stat], |row| row.get(0)));
+
+        Ok(higher as u32 + 1)
+    }
+
+    fn search(&self, search: &str) -> Result<Vec<CustomCommand>> {
+        let conn = &self.reader();
+        let mut stmt = try!(conn.prepare_cached(&format!("SELECT {} FROM commands WHERE name LIKE ?1 \
+                                                 or name LIKE ?2 ORDER BY LOWER(name)", COLUMNS)));
         let search_loose = format!("%{}%", search);
-        let mut rows = try!(stmt.query(&[search, &search_loose]));
+        let mut rows = try!(stmt.query(&[&search, &search_loose]));
 
         let mut commands = Vec::new();
         while let Some(result_row) = rows.next() {
             let row = try!(result_row);
 
-            let cmd = CustomCommand {
-                name: row.get(1),
-                url: row.get(2),
-                owner: row.get(3),
-                stat: row.get(4),
-                created: row.get(5)
-            };
-
-            commands.push(cmd);
+            commands.push(from_row(&row));
         }
 
         Ok(commands)
     }
 
-    pub fn add(&self, name: &String, url: &String, owner: u64) -> Result<(), Error> {
+    fn add(&self, name: &str, url: &str, owner: u64) -> Result<()> {
         let conn = &self.conn.lock().unwrap();
+
+        if try!(exists(conn, name)) {
+            return Err(Error::AlreadyExists(name.to_string()));
+        }
+
         let mut stmt = try!(conn.prepare_cached("INSERT INTO commands (name, url, owner, stat, created) \
                                                       VALUES (:name, :url, :owner, :stat, :created)"));
 
@@ -157,21 +385,24 @@ impl Database {
 
         let owner = owner as i64;
 
-        try!(stmt.execute_named(&[(":name", name), (":url", url), (":owner", &owner),
+        try!(stmt.execute_named(&[(":name", &name), (":url", &url), (":owner", &owner),
                                   (":stat", &0), (":created", &current_time)]));
 
         Ok(())
     }
 
-    pub fn delete(&self, name: &String) -> Result<(), Error> {
+    fn delete(&self, name: &str) -> Result<()> {
         let conn = &self.conn.lock().unwrap();
         let mut stmt = try!(conn.prepare_cached("DELETE FROM commands WHERE name = ?"));
-        try!(stmt.execute(&[name]));
+
+        if try!(stmt.execute(&[&name])) == 0 {
+            return Err(Error::NotFound(name.to_string()));
+        }
 
         Ok(())
     }
 
-    pub fn delete_all(&self) -> Result<(), Error> {
+    fn delete_all(&self) -> Result<()> {
         let conn = &self.conn.lock().unwrap();
         let mut stmt = try!(conn.prepare_cached("DELETE FROM commands"));
         try!(stmt.execute(&[]));
@@ -179,13 +410,220 @@ impl Database {
         Ok(())
     }
 
-    pub fn edit(&self, name: &String, new_name: &String, new_url: &String) -> Result<(), Error> {
+    fn edit(&self, name: &str, new_name: &str, new_url: &str, editor: u64) -> Result<()> {
         let conn = &self.conn.lock().unwrap();
-        let mut stmt = try!(conn.prepare_cached("UPDATE commands SET name = :new_name, url = :new_url WHERE name = :name"));
-        try!(stmt.execute_named(&[(":new_name", new_name), (":new_url", new_url), (":name", name)]));
+
+        if !try!(exists(conn, name)) {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        // keeping the same name only updates the response
+        if new_name != name && try!(exists(conn, new_name)) {
+            return Err(Error::AlreadyExists(new_name.to_string()));
+        }
+
+        let mut stmt = try!(conn.prepare_cached("UPDATE commands SET name = :new_name, url = :new_url, \
+                                                 edited_by = :editor, edited = :edited WHERE name = :name"));
+
+        let current_time = Utc::now().timestamp();
+
+        let editor = editor as i64;
+
+        try!(stmt.execute_named(&[(":new_name", &new_name), (":new_url", &new_url), (":editor", &editor),
+                                  (":edited", &current_time), (":name", &name)]));
+
+        Ok(())
+    }
+
+    fn set_trigger(&self, name: &str, trigger: TriggerKind, pattern: Option<&str>, guild: Option<u64>) -> Result<()> {
+        let conn = &self.conn.lock().unwrap();
+        let mut stmt = try!(conn.prepare_cached("UPDATE commands SET trigger_kind = :trigger_kind, \
+                                                 pattern = :pattern, trigger_guild = :guild WHERE name = :name"));
+
+        let guild = guild.map(|id| id as i64);
+        let updated = try!(stmt.execute_named(&[(":trigger_kind", &trigger.as_str()), (":pattern", &pattern),
+                                                (":guild", &guild), (":name", &name)]));
+
+        if updated == 0 {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn set_actions(&self, name: &str, actions: &Actions) -> Result<()> {
+        let conn = &self.conn.lock().unwrap();
+        let mut stmt = try!(conn.prepare_cached("UPDATE commands SET actions = ? WHERE name = ?"));
+
+        if try!(stmt.execute(&[&actions.to_json(), &name])) == 0 {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn set_script(&self, name: &str, script: Option<&[Step]>) -> Result<()> {
+        let conn = &self.conn.lock().unwrap();
+        let mut stmt = try!(conn.prepare_cached("UPDATE commands SET script = ? WHERE name = ?"));
+
+        if try!(stmt.execute(&[&store::script_to_json(script), &name])) == 0 {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn disabled_channels(&self) -> Result<Vec<u64>> {
+        let conn = &self.reader();
+        let mut stmt = try!(conn.prepare_cached("SELECT id FROM disabled_trigger_channels"));
+        let mut rows = try!(stmt.query(&[]));
+
+        let mut channels = Vec::new();
+        while let Some(result_row) = rows.next() {
+            let row = try!(result_row);
+            let id: i64 = row.get(0);
+
+            channels.push(id as u64);
+        }
+
+        Ok(channels)
+    }
+
+    fn set_channel_triggers(&self, channel: u64, enabled: bool) -> Result<()> {
+        let conn = &self.conn.lock().unwrap();
+        let channel = channel as i64;
+
+        if enabled {
+            try!(conn.execute("DELETE FROM disabled_trigger_channels WHERE id = ?", &[&channel]));
+        } else {
+            try!(conn.execute("INSERT OR IGNORE INTO disabled_trigger_channels (id) VALUES (?)", &[&channel]));
+        }
 
         Ok(())
     }
 
+    fn guild_languages(&self) -> Result<Vec<(u64, String)>> {
+        let conn = &self.reader();
+        let mut stmt = try!(conn.prepare_cached("SELECT id, language FROM guild_languages"));
+        let mut rows = try!(stmt.query(&[]));
+
+        let mut languages = Vec::new();
+        while let Some(result_row) = rows.next() {
+            let row = try!(result_row);
+            let id: i64 = row.get(0);
+
+            languages.push((id as u64, row.get(1)));
+        }
+
+        Ok(languages)
+    }
+
+    fn set_guild_language(&self, guild: u64, language: Option<&str>) -> Result<()> {
+        let conn = &self.conn.lock().unwrap();
+        let guild = guild as i64;
+
+        match language {
+            Some(language) => {
+                try!(conn.execute("INSERT OR REPLACE INTO guild_languages (id, language) VALUES (?, ?)",
+                                  &[&guild, &language]));
+            },
+            None => {
+                try!(conn.execute("DELETE FROM guild_languages WHERE id = ?", &[&guild]));
+            },
+        }
+
+        Ok(())
+    }
+
+    fn backup(&self, path: &Path) -> Result<()> {
+        let conn = &self.conn.lock().unwrap();
+        try!(conn.backup(DatabaseName::Main, path, None));
+
+        Ok(())
+    }
+
+    fn restore(&self, path: &Path) -> Result<()> {
+        try!(validate(path));
+
+        let mut conn = self.conn.lock().unwrap();
+        try!(conn.restore(DatabaseName::Main, path, None::<fn(Progress)>));
+
+        // backups made by older versions can be missing newer columns
+        create_tables(&conn)
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+    use super::*;
+
+    // a path in an empty directory only used by one test
+    fn temp_path(test: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sqlite-test-{}-{}", process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir.join("backup.sqlite3")
+    }
+
+    fn is_invalid<T>(result: Result<T>) -> bool {
+        match result {
+            Err(Error::InvalidInput(_)) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn backups_are_validated() {
+        let path = temp_path("valid");
+        let db = open_in_memory().unwrap();
+        db.add("hello", "world", 1).unwrap();
+        db.backup(&path).unwrap();
+
+        validate(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_columns_are_rejected() {
+        let path = temp_path("missing-column");
+
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch("CREATE TABLE commands (id INTEGER PRIMARY KEY, name TEXT, url TEXT, owner INTEGER,
+                                                       stat INTEGER);
+                                CREATE TABLE servers (id INTEGER PRIMARY KEY, prefix TEXT);").unwrap();
+        }
+
+        assert!(is_invalid(validate(&path)));
+
+        // nothing is restored from an invalid backup
+        let db = open_in_memory().unwrap();
+        db.add("kept", "url", 1).unwrap();
+        assert!(db.restore(&path).is_err());
+        assert!(db.is_command("kept").unwrap());
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected() {
+        let path = temp_path("corrupt");
+        fs::write(&path, vec![0x42; 4096]).unwrap();
+
+        assert!(validate(&path).is_err());
+
+        let db = open_in_memory().unwrap();
+        db.add("kept", "url", 1).unwrap();
+        assert!(db.restore(&path).is_err());
+        assert!(db.is_command("kept").unwrap());
+    }
+
+    #[test]
+    fn directories_are_rejected() {
+        let path = temp_path("directory");
+        fs::create_dir_all(&path).unwrap();
+
+        assert!(is_invalid(validate(&path)));
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use chrono::prelude::*;
+use error::{Error, Result};
+use store;
+use store::{Actions, CommandStore, Creator, CustomCommand, Step, TriggerKind};
+
+// entries are refreshed after this long in case another instance sharing
+// the database changed them
+const TTL_SECS: u64 = 60;
+
+// the whole cache is dropped once it grows this large, mostly to bound
+// the number of misses remembered for messages that aren't commands
+const MAX_ENTRIES: usize = 10_000;
+
+struct Entry {
+    // None if there is no command with the name
+    command: Option<CustomCommand>,
+    cached: Instant
+}
+
+/// Read-through cache of command lookups by name in front of another store.
+///
+/// Lookups that find nothing are cached too, since most messages starting with
+/// the prefix aren't custom commands.  Entries are keyed by the normalized name,
+/// so any change to a command through this store invalidates the lookups of
+/// every spelling of its name.  Lookups themselves stay case sensitive, and
+/// commands are shared between guilds so the guild isn't part of the key.
+pub struct CachedStore<S> {
+    inner: S,
+    // normalized name to the lookups of each exact spelling of it
+    entries: RwLock<HashMap<String, HashMap<String, Entry>>>
+}
+
+impl<S: CommandStore> CachedStore<S> {
+    pub fn new(inner: S) -> CachedStore<S> {
+        CachedStore {
+            inner: inner,
+            entries: RwLock::new(HashMap::new())
+        }
+    }
+
+    fn cached(&self, name: &str) -> Option<Option<CustomCommand>> {
+        let entries = self.entries.read().unwrap();
+
+        match entries.get(&store::normalize(name)).and_then(|names| names.get(name)) {
+            Some(entry) if entry.cached.elapsed() < Duration::from_secs(TTL_SECS) => Some(entry.command.clone()),
+            _ => None,
+        }
+    }
+
+    fn insert(&self, name: &str, command: Option<CustomCommand>) {
+        let mut entries = self.entries.write().unwrap();
+
+        if entries.len() >= MAX_ENTRIES {
+            entries.clear();
+        }
+
+        let names = entries.entry(store::normalize(name)).or_insert_with(HashMap::new);
+        names.insert(name.to_string(), Entry {
+            command: command,
+            cached: Instant::now()
+        });
+    }
+
+    fn invalidate(&self, name: &str) {
+        self.entries.write().unwrap().remove(&store::normalize(name));
+    }
+
+    fn clear(&self) {
+        self.entries.write().unwrap().clear();
+    }
+}
+
+impl<S: CommandStore> CommandStore for CachedStore<S> {
+    fn is_command(&self, name: &str) -> Result<bool> {
+        match self.get(name) {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound(_)) => Ok(false),
+            Err(why) => Err(why),
+        }
+    }
+
+    fn increment(&self, command: &CustomCommand) -> Result<()> {
+        try!(self.inner.increment(command));
+
+        // keep the cached stats in sync instead of looking the command up again
+        let mut entries = self.entries.write().unwrap();
+        let cached = entries.get_mut(&store::normalize(&command.name))
+            .and_then(|names| names.get_mut(&command.name))
+            .and_then(|entry| entry.command.as_mut());
+
+        if let Some(cached) = cached {
+            cached.stat += 1;
+            cached.last_used = Some(Utc::now().timestamp());
+        }
+
+        Ok(())
+    }
+
+    fn all(&self) -> Result<Vec<CustomCommand>> {
+        self.inner.all()
+    }
+
+    fn top(&self) -> Result<Vec<CustomCommand>> {
+        self.inner.top()
+    }
+
+    fn creators(&self) -> Result<Vec<Creator>> {
+        self.inner.creators()
+    }
+
+    fn by_owner(&self, owner: u64) -> Result<Vec<CustomCommand>> {
+        self.inner.by_owner(owner)
+    }
+
+    fn get(&self, name: &str) -> Result<CustomCommand> {
+        if let Some(command) = self.cached(name) {
+            return command.ok_or_else(|| Error::NotFound(name.to_string()));
+        }
+
+        let result = self.inner.get(name);
+
+        match result {
+            Ok(ref command) => self.insert(name, Some(command.clone())),
+            Err(Error::NotFound(_)) => self.insert(name, None),
+            Err(_) => {},
+        }
+
+        result
+    }
+
+    fn rank(&self, command: &CustomCommand) -> Result<u32> {
+        self.inner.rank(command)
+    }
+
+    fn search(&self, search: &str) -> Result<Vec<CustomCommand>> {
+        self.inner.search(search)
+    }
+
+    fn add(&self, name: &str, url: &str, owner: u64) -> Result<()> {
+        let result = self.inner.add(name, url, owner);
+        self.invalidate(name);
+
+        result
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        let result = self.inner.delete(name);
+        self.invalidate(name);
+
+        result
+    }
+
+    fn delete_all(&self) -> Result<()> {
+        let result = self.inner.delete_all();
+        self.clear();
+
+        result
+    }
+
+    fn edit(&self, name: &str, new_name: &str, new_url: &str, editor: u64) -> Result<()> {
+        let result = self.inner.edit(name, new_name, new_url, editor);
+        self.invalidate(name);
+        self.invalidate(new_name);
+
+        result
+    }
+
+    fn set_trigger(&self, name: &str, trigger: TriggerKind, pattern: Option<&str>, guild: Option<u64>) -> Result<()> {
+        let result = self.inner.set_trigger(name, trigger, pattern, guild);
+        self.invalidate(name);
+
+        result
+    }
+
+    fn set_actions(&self, name: &str, actions: &Actions) -> Result<()> {
+        let result = self.inner.set_actions(name, actions);
+        self.invalidate(name);
+
+        result
+    }
+
+    fn set_script(&self, name: &str, script: Option<&[Step]>) -> Result<()> {
+        let result = self.inner.set_script(name, script);
+        self.invalidate(name);
+
+        result
+    }
+
+    fn disabled_channels(&self) -> Result<Vec<u64>> {
+        self.inner.disabled_channels()
+    }
+
+    fn set_channel_triggers(&self, channel: u64, enabled: bool) -> Result<()> {
+        self.inner.set_channel_triggers(channel, enabled)
+    }
+
+    fn guild_languages(&self) -> Result<Vec<(u64, String)>> {
+        self.inner.guild_languages()
+    }
+
+    fn set_guild_language(&self, guild: u64, language: Option<&str>) -> Result<()> {
+        self.inner.set_guild_language(guild, language)
+    }
+
+    fn backup(&self, path: &Path) -> Result<()> {
+        self.inner.backup(path)
+    }
+
+    fn restore(&self, path: &Path) -> Result<()> {
+        let result = self.inner.restore(path);
+        self.clear();
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::*;
+    use sqlite;
+    use store::memory::MemoryStore;
+
+    // counts the lookups that reach the store behind the cache
+    struct Counting<S> {
+        inner: S,
+        gets: AtomicUsize
+    }
+
+    impl<S> Counting<S> {
+        fn new(inner: S) -> Counting<S> {
+            Counting {
+                inner: inner,
+                gets: AtomicUsize::new(0)
+            }
+        }
+    }
+
+    // passes everything through, counting lookups
+    impl<S: CommandStore> CommandStore for Counting<S> {
+        fn is_command(&self, name: &str) -> Result<bool> {
+            self.inner.is_command(name)
+        }
+
+        fn increment(&self, command: &CustomCommand) -> Result<()> {
+            self.inner.increment(command)
+        }
+
+        fn all(&self) -> Result<Vec<CustomCommand>> {
+            self.inner.all()
+        }
+
+        fn top(&self) -> Result<Vec<CustomCommand>> {
+            self.inner.top()
+        }
+
+        fn creators(&self) -> Result<Vec<Creator>> {
+            self.inner.creators()
+        }
+
+        fn by_owner(&self, owner: u64) -> Result<Vec<CustomCommand>> {
+            self.inner.by_owner(owner)
+        }
+
+        fn get(&self, name: &str) -> Result<CustomCommand> {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+            self.inner.get(name)
+        }
+
+        fn rank(&self, command: &CustomCommand) -> Result<u32> {
+            self.inner.rank(command)
+        }
+
+        fn search(&self, search: &str) -> Result<Vec<CustomCommand>> {
+            self.inner.search(search)
+        }
+
+        fn add(&self, name: &str, url: &str, owner: u64) -> Result<()> {
+            self.inner.add(name, url, owner)
+        }
+
+        fn delete(&self, name: &str) -> Result<()> {
+            self.inner.delete(name)
+        }
+
+        fn delete_all(&self) -> Result<()> {
+            self.inner.delete_all()
+        }
+
+        fn edit(&self, name: &str, new_name: &str, new_url: &str, editor: u64) -> Result<()> {
+            self.inner.edit(name, new_name, new_url, editor)
+        }
+
+        fn set_trigger(&self, name: &str, trigger: TriggerKind, pattern: Option<&str>,
+                       guild: Option<u64>) -> Result<()> {
+            self.inner.set_trigger(name, trigger, pattern, guild)
+        }
+
+        fn set_actions(&self, name: &str, actions: &Actions) -> Result<()> {
+            self.inner.set_actions(name, actions)
+        }
+
+        fn set_script(&self, name: &str, script: Option<&[Step]>) -> Result<()> {
+            self.inner.set_script(name, script)
+        }
+
+        fn disabled_channels(&self) -> Result<Vec<u64>> {
+            self.inner.disabled_channels()
+        }
+
+        fn set_channel_triggers(&self, channel: u64, enabled: bool) -> Result<()> {
+            self.inner.set_channel_triggers(channel, enabled)
+        }
+
+        fn guild_languages(&self) -> Result<Vec<(u64, String)>> {
+            self.inner.guild_languages()
+        }
+
+        fn set_guild_language(&self, guild: u64, language: Option<&str>) -> Result<()> {
+            self.inner.set_guild_language(guild, language)
+        }
+    }
+
+    fn gets<S>(cache: &CachedStore<Counting<S>>) -> usize {
+        cache.inner.gets.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn conformance() {
+        store::tests::conformance(&CachedStore::new(MemoryStore::new()));
+        store::tests::conformance(&CachedStore::new(sqlite::open_in_memory().unwrap()));
+    }
+
+    #[test]
+    fn lookups_and_misses_are_cached() {
+        let cache = CachedStore::new(Counting::new(MemoryStore::new()));
+        cache.add("hello", "world", 1).unwrap();
+
+        for _ in 0..5 {
+            assert_eq!(cache.get("hello").unwrap().url, "world");
+            assert!(!cache.is_command("missing").unwrap());
+        }
+
+        assert_eq!(gets(&cache), 2);
+    }
+
+    #[test]
+    fn changes_invalidate_every_spelling() {
+        let cache = CachedStore::new(Counting::new(MemoryStore::new()));
+        cache.add("hello", "world", 1).unwrap();
+
+        assert!(cache.get("hello").is_ok());
+        assert!(cache.get("Hello").is_err());
+
+        // renaming to another spelling drops both cached lookups
+        cache.edit("hello", "Hello", "changed", 1).unwrap();
+        assert!(cache.get("hello").is_err());
+        assert_eq!(cache.get("Hello").unwrap().url, "changed");
+
+        cache.delete("HELLO").unwrap_err();
+        cache.delete("Hello").unwrap();
+        assert!(cache.get("Hello").is_err());
+    }
+
+    #[test]
+    fn increments_update_cached_commands() {
+        let cache = CachedStore::new(Counting::new(MemoryStore::new()));
+        cache.add("hello", "world", 1).unwrap();
+
+        let cmd = cache.get("hello").unwrap();
+        cache.increment(&cmd).unwrap();
+        cache.increment(&cmd).unwrap();
+
+        assert_eq!(cache.get("hello").unwrap().stat, 2);
+        assert_eq!(gets(&cache), 1);
+    }
+
+    // lookups on the message path, most messages with the prefix aren't custom commands.
+    // Only reports the timings, run with `cargo test --release -- --ignored lookup_latency --nocapture`
+    #[test]
+    #[ignore]
+    fn lookup_latency() {
+        const LOOKUPS: usize = 10_000;
+        let names: Vec<String> = (0..100).map(|i| format!("command{}", i)).collect();
+
+        let db = sqlite::open_in_memory().unwrap();
+        let cache = CachedStore::new(sqlite::open_in_memory().unwrap());
+
+        for name in &names {
+            db.add(name, "url", 1).unwrap();
+            cache.add(name, "url", 1).unwrap();
+        }
+
+        let time = |store: &CommandStore| {
+            let start = Instant::now();
+
+            for i in 0..LOOKUPS {
+                let name = if i % 2 == 0 { names[i % names.len()].as_str() } else { "help" };
+                let _ = store.get(name);
+            }
+
+            start.elapsed()
+        };
+
+        let uncached = time(&db);
+        let cached = time(&cache);
+
+        println!("{} lookups: {:?} without the cache, {:?} with it", LOOKUPS, uncached, cached);
+    }
+}
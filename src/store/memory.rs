@@ -0,0 +1,257 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use chrono::prelude::*;
+use error::{Error, Result};
+use store::{Actions, CommandStore, Creator, CustomCommand, Step, TriggerKind};
+
+/// Keeps commands in memory, nothing is persisted
+pub struct MemoryStore {
+    commands: Mutex<Vec<CustomCommand>>,
+    disabled_channels: Mutex<HashSet<u64>>,
+    languages: Mutex<HashMap<u64, String>>
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            commands: Mutex::new(Vec::new()),
+            disabled_channels: Mutex::new(HashSet::new()),
+            languages: Mutex::new(HashMap::new())
+        }
+    }
+}
+
+fn sorted_by_name(mut commands: Vec<CustomCommand>) -> Vec<CustomCommand> {
+    commands.sort_by_key(|cmd| cmd.name.to_lowercase());
+    commands
+}
+
+fn sorted_by_stat(mut commands: Vec<CustomCommand>) -> Vec<CustomCommand> {
+    commands.sort_by(|a, b| b.stat.cmp(&a.stat));
+    commands
+}
+
+impl CommandStore for MemoryStore {
+    fn is_command(&self, name: &str) -> Result<bool> {
+        let commands = self.commands.lock().unwrap();
+        Ok(commands.iter().any(|cmd| cmd.name == name))
+    }
+
+    fn increment(&self, command: &CustomCommand) -> Result<()> {
+        let mut commands = self.commands.lock().unwrap();
+
+        if let Some(cmd) = commands.iter_mut().find(|cmd| cmd.name == command.name) {
+            cmd.stat += 1;
+            cmd.last_used = Some(Utc::now().timestamp());
+        }
+
+        Ok(())
+    }
+
+    fn all(&self) -> Result<Vec<CustomCommand>> {
+        let commands = self.commands.lock().unwrap();
+        Ok(sorted_by_name(commands.clone()))
+    }
+
+    fn top(&self) -> Result<Vec<CustomCommand>> {
+        let commands = self.commands.lock().unwrap();
+        Ok(sorted_by_stat(commands.clone()))
+    }
+
+    fn creators(&self) -> Result<Vec<Creator>> {
+        let commands = self.commands.lock().unwrap();
+
+        let mut by_owner: HashMap<i64, Creator> = HashMap::new();
+        for cmd in commands.iter() {
+            let creator = by_owner.entry(cmd.owner).or_insert(Creator {
+                owner: cmd.owner,
+                commands: 0,
+                uses: 0
+            });
+
+            creator.commands += 1;
+            creator.uses += cmd.stat;
+        }
+
+        let mut creators: Vec<Creator> = by_owner.into_iter().map(|(_, creator)| creator).collect();
+        creators.sort_by(|a, b| (b.commands, b.uses).cmp(&(a.commands, a.uses)));
+
+        Ok(creators)
+    }
+
+    fn by_owner(&self, owner: u64) -> Result<Vec<CustomCommand>> {
+        let commands = self.commands.lock().unwrap();
+
+        let owned = commands.iter()
+            .filter(|cmd| cmd.is_owner(owner))
+            .cloned()
+            .collect();
+
+        Ok(sorted_by_stat(owned))
+    }
+
+    fn get(&self, name: &str) -> Result<CustomCommand> {
+        let commands = self.commands.lock().unwrap();
+
+        commands.iter()
+            .find(|cmd| cmd.name == name)
+            .cloned()
+            .ok_or_else(|| Error::NotFound(name.to_string()))
+    }
+
+    fn rank(&self, command: &CustomCommand) -> Result<u32> {
+        let commands = self.commands.lock().unwrap();
+        let higher = commands.iter().filter(|cmd| cmd.stat > command.stat).count();
+
+        Ok(higher as u32 + 1)
+    }
+
+    fn search(&self, search: &str) -> Result<Vec<CustomCommand>> {
+        let commands = self.commands.lock().unwrap();
+        let search = search.to_lowercase();
+
+        let results = commands.iter()
+            .filter(|cmd| cmd.name.to_lowercase().contains(&search))
+            .cloned()
+            .collect();
+
+        Ok(sorted_by_name(results))
+    }
+
+    fn add(&self, name: &str, url: &str, owner: u64) -> Result<()> {
+        let mut commands = self.commands.lock().unwrap();
+
+        if commands.iter().any(|cmd| cmd.name == name) {
+            return Err(Error::AlreadyExists(name.to_string()));
+        }
+
+        commands.push(CustomCommand {
+            name: name.to_string(),
+            url: url.to_string(),
+            owner: owner as i64,
+            stat: 0,
+            created: Utc::now().timestamp() as u32,
+            last_used: None,
+            edited_by: None,
+            edited: None,
+            trigger: TriggerKind::Prefix,
+            pattern: None,
+            trigger_guild: None,
+            actions: Actions::default(),
+            script: None
+        });
+
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        let mut commands = self.commands.lock().unwrap();
+
+        let len = commands.len();
+        commands.retain(|cmd| cmd.name != name);
+
+        if commands.len() == len {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn delete_all(&self) -> Result<()> {
+        self.commands.lock().unwrap().clear();
+
+        Ok(())
+    }
+
+    fn edit(&self, name: &str, new_name: &str, new_url: &str, editor: u64) -> Result<()> {
+        let mut commands = self.commands.lock().unwrap();
+
+        if !commands.iter().any(|cmd| cmd.name == name) {
+            return Err(Error::NotFound(name.to_string()));
+        }
+
+        // keeping the same name only updates the response
+        if new_name != name && commands.iter().any(|cmd| cmd.name == new_name) {
+            return Err(Error::AlreadyExists(new_name.to_string()));
+        }
+
+        let cmd = commands.iter_mut().find(|cmd| cmd.name == name).unwrap();
+
+        cmd.name = new_name.to_string();
+        cmd.url = new_url.to_string();
+        cmd.edited_by = Some(editor as i64);
+        cmd.edited = Some(Utc::now().timestamp());
+
+        Ok(())
+    }
+
+    fn set_trigger(&self, name: &str, trigger: TriggerKind, pattern: Option<&str>, guild: Option<u64>) -> Result<()> {
+        let mut commands = self.commands.lock().unwrap();
+
+        let cmd = match commands.iter_mut().find(|cmd| cmd.name == name) {
+            Some(cmd) => cmd,
+            None => return Err(Error::NotFound(name.to_string())),
+        };
+
+        cmd.trigger = trigger;
+        cmd.pattern = pattern.map(|pattern| pattern.to_string());
+        cmd.trigger_guild = guild;
+
+        Ok(())
+    }
+
+    fn set_actions(&self, name: &str, actions: &Actions) -> Result<()> {
+        let mut commands = self.commands.lock().unwrap();
+
+        match commands.iter_mut().find(|cmd| cmd.name == name) {
+            Some(cmd) => cmd.actions = actions.clone(),
+            None => return Err(Error::NotFound(name.to_string())),
+        }
+
+        Ok(())
+    }
+
+    fn set_script(&self, name: &str, script: Option<&[Step]>) -> Result<()> {
+        let mut commands = self.commands.lock().unwrap();
+
+        match commands.iter_mut().find(|cmd| cmd.name == name) {
+            Some(cmd) => cmd.script = script.map(|steps| steps.to_vec()),
+            None => return Err(Error::NotFound(name.to_string())),
+        }
+
+        Ok(())
+    }
+
+    fn disabled_channels(&self) -> Result<Vec<u64>> {
+        let disabled = self.disabled_channels.lock().unwrap();
+        Ok(disabled.iter().cloned().collect())
+    }
+
+    fn set_channel_triggers(&self, channel: u64, enabled: bool) -> Result<()> {
+        let mut disabled = self.disabled_channels.lock().unwrap();
+
+        if enabled {
+            disabled.remove(&channel);
+        } else {
+            disabled.insert(channel);
+        }
+
+        Ok(())
+    }
+
+    fn guild_languages(&self) -> Result<Vec<(u64, String)>> {
+        let languages = self.languages.lock().unwrap();
+        Ok(languages.iter().map(|(guild, language)| (*guild, language.clone())).collect())
+    }
+
+    fn set_guild_language(&self, guild: u64, language: Option<&str>) -> Result<()> {
+        let mut languages = self.languages.lock().unwrap();
+
+        match language {
+            Some(language) => languages.insert(guild, language.to_string()),
+            None => languages.remove(&guild),
+        };
+
+        Ok(())
+    }
+}
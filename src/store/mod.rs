@@ -0,0 +1,546 @@
+use std::path::Path;
+use std::sync::Arc;
+use serenity::prelude::Context;
+use typemap::Key;
+use serde_json;
+use error::{Error, Result};
+use sqlite;
+#[cfg(feature = "postgres")]
+use postgres;
+
+pub mod cache;
+pub mod memory;
+
+/// What a message has to match for a command to respond
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerKind {
+    /// The prefix followed by the command name
+    Prefix,
+    /// The whole message, ignoring case
+    Exact,
+    /// Anywhere in the message, ignoring case
+    Contains,
+    /// The start of the message, ignoring case
+    StartsWith,
+    /// A regular expression, ignoring case
+    Regex
+}
+
+impl TriggerKind {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            TriggerKind::Prefix => "prefix",
+            TriggerKind::Exact => "exact",
+            TriggerKind::Contains => "contains",
+            TriggerKind::StartsWith => "starts-with",
+            TriggerKind::Regex => "regex",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<TriggerKind> {
+        match kind.to_lowercase().as_str() {
+            "prefix" => Some(TriggerKind::Prefix),
+            "exact" => Some(TriggerKind::Exact),
+            "contains" => Some(TriggerKind::Contains),
+            "starts-with" | "startswith" => Some(TriggerKind::StartsWith),
+            "regex" => Some(TriggerKind::Regex),
+            _ => None,
+        }
+    }
+}
+
+/// What a command does besides sending its response in the channel it was used in
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Actions {
+    /// Emojis added to the message that triggered the command
+    #[serde(default)]
+    pub reactions: Vec<String>,
+    /// Deletes the message that triggered the command
+    #[serde(default)]
+    pub delete_trigger: bool,
+    /// Sends the response to the user in a direct message
+    #[serde(default)]
+    pub dm: bool,
+    /// Sends the response to another channel in the same guild
+    #[serde(default)]
+    pub channel: Option<u64>,
+    /// Doesn't send the response at all
+    #[serde(default)]
+    pub silent: bool
+}
+
+impl Actions {
+    /// Reads actions stored as JSON, `None` for commands without actions
+    pub fn from_json(json: Option<String>) -> Actions {
+        match json {
+            Some(json) => serde_json::from_str(&json).unwrap_or_else(|why| {
+                warn!("Ignoring invalid command actions {}: {}", json, why);
+                Actions::default()
+            }),
+            None => Actions::default(),
+        }
+    }
+
+    /// Actions as stored, `None` if there are none
+    pub fn to_json(&self) -> Option<String> {
+        if *self == Actions::default() {
+            return None;
+        }
+
+        serde_json::to_string(self).ok()
+    }
+}
+
+/// A step of a script sent instead of a command's response
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Step {
+    Text {
+        content: String
+    },
+    Embed {
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default)]
+        description: Option<String>,
+        #[serde(default)]
+        colour: Option<u32>,
+        #[serde(default)]
+        image: Option<String>
+    },
+    /// Reacts to the message that triggered the command
+    React {
+        emoji: String
+    },
+    Delay {
+        seconds: u64
+    },
+    /// Shows the bot typing until the next message or for a few seconds
+    Typing
+}
+
+/// Reads a script stored as JSON, `None` for commands without a script
+pub fn script_from_json(json: Option<String>) -> Option<Vec<Step>> {
+    json.and_then(|json| match serde_json::from_str(&json) {
+        Ok(steps) => Some(steps),
+        Err(why) => {
+            warn!("Ignoring invalid command script {}: {}", json, why);
+            None
+        },
+    })
+}
+
+pub fn script_to_json(script: Option<&[Step]>) -> Option<String> {
+    script.and_then(|steps| serde_json::to_string(steps).ok())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomCommand {
+    pub name: String,
+    pub url: String,
+    pub owner: i64,
+    pub stat: u32,
+    pub created: u32,
+    pub last_used: Option<i64>,
+    pub edited_by: Option<i64>,
+    pub edited: Option<i64>,
+    pub trigger: TriggerKind,
+    /// Matched against messages for triggers other than `Prefix`
+    pub pattern: Option<String>,
+    /// The guild whose messages the trigger is matched against, commands are shared
+    /// between guilds but triggers only respond where they were set
+    pub trigger_guild: Option<u64>,
+    pub actions: Actions,
+    /// Sent instead of the response if set
+    pub script: Option<Vec<Step>>
+}
+
+impl CustomCommand {
+    pub fn is_owner(&self, id: u64) -> bool {
+        id == self.owner as u64
+    }
+}
+
+/// Summary of the commands created by a single user
+#[derive(Clone, Debug, PartialEq)]
+pub struct Creator {
+    pub owner: i64,
+    pub commands: u32,
+    pub uses: u32
+}
+
+/// The form of a command name used to group its spellings, lookups by name are still exact
+pub fn normalize(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Storage for custom commands.
+///
+/// Lookups by name are exact, `get`, `delete` and `edit` return `Error::NotFound`
+/// for missing commands and `add` and `edit` return `Error::AlreadyExists` if the
+/// (new) name is taken.
+pub trait CommandStore: Send + Sync {
+    fn is_command(&self, name: &str) -> Result<bool>;
+
+    /// Counts a use of the command and updates when it was last used
+    fn increment(&self, command: &CustomCommand) -> Result<()>;
+
+    /// All commands sorted by name, ignoring case
+    fn all(&self) -> Result<Vec<CustomCommand>>;
+
+    /// All commands, most used first
+    fn top(&self) -> Result<Vec<CustomCommand>>;
+
+    /// Users ranked by the number of commands they created, then by total uses
+    fn creators(&self) -> Result<Vec<Creator>>;
+
+    /// Commands created by a user, most used first
+    fn by_owner(&self, owner: u64) -> Result<Vec<CustomCommand>>;
+
+    fn get(&self, name: &str) -> Result<CustomCommand>;
+
+    /// Position of a command when sorted by usage, starting at 1
+    fn rank(&self, command: &CustomCommand) -> Result<u32>;
+
+    /// Commands with names containing the search, ignoring case, sorted by name
+    fn search(&self, search: &str) -> Result<Vec<CustomCommand>>;
+
+    fn add(&self, name: &str, url: &str, owner: u64) -> Result<()>;
+
+    fn delete(&self, name: &str) -> Result<()>;
+
+    fn delete_all(&self) -> Result<()>;
+
+    fn edit(&self, name: &str, new_name: &str, new_url: &str, editor: u64) -> Result<()>;
+
+    /// Changes what messages a command responds to, the pattern is stored as given
+    /// and only matched in messages from `guild`
+    fn set_trigger(&self, name: &str, trigger: TriggerKind, pattern: Option<&str>, guild: Option<u64>) -> Result<()>;
+
+    fn set_actions(&self, name: &str, actions: &Actions) -> Result<()>;
+
+    /// Sets the script sent instead of the response, `None` removes it
+    fn set_script(&self, name: &str, script: Option<&[Step]>) -> Result<()>;
+
+    /// Channels where commands with triggers other than `Prefix` don't respond
+    fn disabled_channels(&self) -> Result<Vec<u64>>;
+
+    fn set_channel_triggers(&self, channel: u64, enabled: bool) -> Result<()>;
+
+    /// Languages chosen by guilds, guilds using the default language aren't included
+    fn guild_languages(&self) -> Result<Vec<(u64, String)>>;
+
+    /// Sets the language of a guild, `None` goes back to the default
+    fn set_guild_language(&self, guild: u64, language: Option<&str>) -> Result<()>;
+
+    /// Writes a copy of the store to a file while it stays in use
+    fn backup(&self, _path: &Path) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    /// Replaces the contents of the store with a backup written by `backup`
+    fn restore(&self, _path: &Path) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+}
+
+/// The command store shared between handlers
+pub struct Store;
+
+impl Key for Store {
+    type Value = Arc<CommandStore>;
+}
+
+/// Gets the command store without holding the lock on the context data
+pub fn get(ctx: &Context) -> Arc<CommandStore> {
+    let data = ctx.data.lock();
+    data.get::<Store>().unwrap().clone()
+}
+
+/// Whether a database url selects the PostgreSQL store
+pub fn is_postgres_url(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+/// Opens the store selected by the database url, defaulting to SQLite.
+///
+/// `memory://` keeps commands in memory only, `postgres://` connects to PostgreSQL
+/// if built with the `postgres` feature, anything else uses the SQLite database.
+pub fn connect(url: &str, sqlite_settings: &sqlite::Settings) -> Result<Arc<CommandStore>> {
+    if url.starts_with("memory://") {
+        info!("Using in-memory command store");
+        return Ok(Arc::new(memory::MemoryStore::new()));
+    }
+
+    if is_postgres_url(url) {
+        return connect_postgres(url);
+    }
+
+    let db = try!(sqlite::open(sqlite_settings));
+    Ok(Arc::new(cache::CachedStore::new(db)))
+}
+
+#[cfg(feature = "postgres")]
+fn connect_postgres(url: &str) -> Result<Arc<CommandStore>> {
+    info!("Using PostgreSQL command store");
+
+    let db = try!(postgres::connect(url));
+    Ok(Arc::new(cache::CachedStore::new(db)))
+}
+
+#[cfg(not(feature = "postgres"))]
+fn connect_postgres(_: &str) -> Result<Arc<CommandStore>> {
+    Err(Error::invalid_input(::helpers::get_error(::strings::Error::DatabasePostgresUnsupported)))
+}
+
+/// Behaviour shared by every store, run against each of them
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use store::memory::MemoryStore;
+
+    fn not_found<T>(result: Result<T>) -> bool {
+        match result {
+            Err(Error::NotFound(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn already_exists<T>(result: Result<T>) -> bool {
+        match result {
+            Err(Error::AlreadyExists(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn names(commands: Vec<CustomCommand>) -> Vec<String> {
+        commands.into_iter().map(|cmd| cmd.name).collect()
+    }
+
+    fn use_command(store: &CommandStore, name: &str, times: usize) {
+        for _ in 0..times {
+            let cmd = store.get(name).unwrap();
+            store.increment(&cmd).unwrap();
+        }
+    }
+
+    /// Runs every check against a store, commands in it are deleted
+    pub fn conformance(store: &CommandStore) {
+        add_and_get(store);
+        edit(store);
+        delete(store);
+        usage(store);
+        all_and_search(store);
+        creators(store);
+        set_fields(store);
+        channel_triggers(store);
+        guild_languages(store);
+
+        store.delete_all().unwrap();
+    }
+
+    fn add_and_get(store: &CommandStore) {
+        store.delete_all().unwrap();
+
+        store.add("hello", "world", 1).unwrap();
+
+        let cmd = store.get("hello").unwrap();
+        assert_eq!(cmd.name, "hello");
+        assert_eq!(cmd.url, "world");
+        assert_eq!(cmd.owner, 1);
+        assert_eq!(cmd.stat, 0);
+        assert_eq!(cmd.last_used, None);
+        assert_eq!((cmd.edited_by, cmd.edited), (None, None));
+        assert_eq!((cmd.trigger, cmd.pattern, cmd.trigger_guild), (TriggerKind::Prefix, None, None));
+        assert_eq!(cmd.actions, Actions::default());
+        assert_eq!(cmd.script, None);
+
+        assert!(store.is_command("hello").unwrap());
+        assert!(!store.is_command("missing").unwrap());
+
+        assert!(already_exists(store.add("hello", "again", 2)));
+        assert_eq!(store.get("hello").unwrap().url, "world");
+
+        // names are exact
+        assert!(not_found(store.get("missing")));
+        assert!(not_found(store.get("HELLO")));
+    }
+
+    fn edit(store: &CommandStore) {
+        store.delete_all().unwrap();
+
+        store.add("a", "first", 1).unwrap();
+        store.add("b", "second", 1).unwrap();
+
+        store.edit("a", "a", "changed", 2).unwrap();
+        let cmd = store.get("a").unwrap();
+        assert_eq!(cmd.url, "changed");
+        assert_eq!(cmd.owner, 1);
+        assert_eq!(cmd.edited_by, Some(2));
+        assert!(cmd.edited.is_some());
+
+        store.edit("a", "c", "renamed", 2).unwrap();
+        assert_eq!(store.get("c").unwrap().url, "renamed");
+        assert!(not_found(store.get("a")));
+
+        assert!(already_exists(store.edit("c", "b", "taken", 2)));
+        assert_eq!(store.get("c").unwrap().url, "renamed");
+
+        assert!(not_found(store.edit("missing", "other", "url", 2)));
+    }
+
+    fn delete(store: &CommandStore) {
+        store.delete_all().unwrap();
+
+        store.add("a", "url", 1).unwrap();
+        store.add("b", "url", 1).unwrap();
+
+        store.delete("a").unwrap();
+        assert!(not_found(store.get("a")));
+        assert!(not_found(store.delete("a")));
+        assert!(store.is_command("b").unwrap());
+
+        store.delete_all().unwrap();
+        assert!(store.all().unwrap().is_empty());
+    }
+
+    fn usage(store: &CommandStore) {
+        store.delete_all().unwrap();
+
+        store.add("x", "url", 1).unwrap();
+        store.add("y", "url", 1).unwrap();
+        store.add("z", "url", 1).unwrap();
+
+        use_command(store, "x", 3);
+        use_command(store, "y", 1);
+
+        let x = store.get("x").unwrap();
+        assert_eq!(x.stat, 3);
+        assert!(x.last_used.is_some());
+
+        assert_eq!(names(store.top().unwrap()), vec!["x", "y", "z"]);
+
+        assert_eq!(store.rank(&x).unwrap(), 1);
+        assert_eq!(store.rank(&store.get("y").unwrap()).unwrap(), 2);
+        assert_eq!(store.rank(&store.get("z").unwrap()).unwrap(), 3);
+    }
+
+    fn all_and_search(store: &CommandStore) {
+        store.delete_all().unwrap();
+
+        for name in &["gamma", "Beta", "alphabet", "alpha"] {
+            store.add(name, "url", 1).unwrap();
+        }
+
+        // sorted by name ignoring case
+        assert_eq!(names(store.all().unwrap()), vec!["alpha", "alphabet", "Beta", "gamma"]);
+
+        assert_eq!(names(store.search("ALPH").unwrap()), vec!["alpha", "alphabet"]);
+        assert_eq!(names(store.search("et").unwrap()), vec!["alphabet", "Beta"]);
+        assert!(store.search("missing").unwrap().is_empty());
+    }
+
+    fn creators(store: &CommandStore) {
+        store.delete_all().unwrap();
+
+        store.add("a1", "url", 1).unwrap();
+        store.add("a2", "url", 1).unwrap();
+        store.add("b1", "url", 2).unwrap();
+        store.add("c1", "url", 3).unwrap();
+        use_command(store, "b1", 5);
+
+        // most commands first, then most uses
+        assert_eq!(store.creators().unwrap(), vec![
+            Creator { owner: 1, commands: 2, uses: 0 },
+            Creator { owner: 2, commands: 1, uses: 5 },
+            Creator { owner: 3, commands: 1, uses: 0 },
+        ]);
+
+        let mut owned = names(store.by_owner(1).unwrap());
+        owned.sort();
+        assert_eq!(owned, vec!["a1", "a2"]);
+        assert_eq!(names(store.by_owner(2).unwrap()), vec!["b1"]);
+        assert!(store.by_owner(9).unwrap().is_empty());
+    }
+
+    fn set_fields(store: &CommandStore) {
+        store.delete_all().unwrap();
+
+        store.add("cmd", "url", 1).unwrap();
+
+        // guild ids past i64::MAX survive the round trip through signed columns
+        let guild = u64::max_value() - 1;
+        store.set_trigger("cmd", TriggerKind::Regex, Some("^hi"), Some(guild)).unwrap();
+        let cmd = store.get("cmd").unwrap();
+        assert_eq!((cmd.trigger, cmd.pattern), (TriggerKind::Regex, Some("^hi".to_string())));
+        assert_eq!(cmd.trigger_guild, Some(guild));
+
+        store.set_trigger("cmd", TriggerKind::Prefix, None, None).unwrap();
+        let cmd = store.get("cmd").unwrap();
+        assert_eq!((cmd.trigger, cmd.pattern, cmd.trigger_guild), (TriggerKind::Prefix, None, None));
+
+        let actions = Actions {
+            reactions: vec!["👍".to_string()],
+            delete_trigger: true,
+            dm: false,
+            channel: Some(5),
+            silent: false,
+        };
+        store.set_actions("cmd", &actions).unwrap();
+        assert_eq!(store.get("cmd").unwrap().actions, actions);
+
+        store.set_actions("cmd", &Actions::default()).unwrap();
+        assert_eq!(store.get("cmd").unwrap().actions, Actions::default());
+
+        let script = vec![
+            Step::Text { content: "hi".to_string() },
+            Step::Delay { seconds: 1 },
+            Step::Typing,
+        ];
+        store.set_script("cmd", Some(&script)).unwrap();
+        assert_eq!(store.get("cmd").unwrap().script, Some(script));
+
+        store.set_script("cmd", None).unwrap();
+        assert_eq!(store.get("cmd").unwrap().script, None);
+
+        assert!(not_found(store.set_trigger("missing", TriggerKind::Exact, Some("hi"), Some(1))));
+        assert!(not_found(store.set_actions("missing", &actions)));
+        assert!(not_found(store.set_script("missing", None)));
+    }
+
+    fn channel_triggers(store: &CommandStore) {
+        store.set_channel_triggers(10, false).unwrap();
+        assert!(store.disabled_channels().unwrap().contains(&10));
+
+        // disabling twice is fine
+        store.set_channel_triggers(10, false).unwrap();
+
+        store.set_channel_triggers(10, true).unwrap();
+        assert!(!store.disabled_channels().unwrap().contains(&10));
+
+        store.set_channel_triggers(10, true).unwrap();
+    }
+
+    fn guild_languages(store: &CommandStore) {
+        store.set_guild_language(20, Some("de")).unwrap();
+        assert!(store.guild_languages().unwrap().contains(&(20, "de".to_string())));
+
+        store.set_guild_language(20, Some("fr")).unwrap();
+        let languages = store.guild_languages().unwrap();
+        assert!(languages.contains(&(20, "fr".to_string())));
+        assert!(!languages.contains(&(20, "de".to_string())));
+
+        store.set_guild_language(20, None).unwrap();
+        assert!(store.guild_languages().unwrap().iter().all(|&(guild, _)| guild != 20));
+    }
+
+    #[test]
+    fn memory_store() {
+        conformance(&MemoryStore::new());
+    }
+
+    #[test]
+    fn sqlite_store() {
+        conformance(&sqlite::open_in_memory().unwrap());
+    }
+}
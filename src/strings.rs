@@ -0,0 +1,178 @@
+//! Keys of the strings in the locale files, so a missing or misspelled key is a
+//! compile error instead of an empty message.  Each key lists the names of the
+//! placeholders its string uses, like `{name}`, which are checked when the
+//! locales are loaded.
+
+/// A key of a string in one section of the locale files
+pub trait Text: Copy {
+    /// Section of the locale files the key is in
+    fn section() -> &'static str;
+
+    /// Every key of the section
+    fn all() -> &'static [Self];
+
+    fn key(&self) -> &'static str;
+
+    /// Names of the placeholders the string has to contain
+    fn placeholders(&self) -> &'static [&'static str];
+}
+
+macro_rules! strings {
+    ($(#[$attr:meta])* $name:ident, $section:tt {
+        $($variant:ident => $key:tt [$($placeholder:tt),*],)*
+    }) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)*
+        }
+
+        impl Text for $name {
+            fn section() -> &'static str {
+                $section
+            }
+
+            fn all() -> &'static [$name] {
+                &[$($name::$variant,)*]
+            }
+
+            fn key(&self) -> &'static str {
+                match *self {
+                    $($name::$variant => $key,)*
+                }
+            }
+
+            fn placeholders(&self) -> &'static [&'static str] {
+                match *self {
+                    $($name::$variant => &[$($placeholder),*],)*
+                }
+            }
+        }
+    };
+}
+
+strings! {
+    /// Replies and messages that aren't errors
+    Info, "info" {
+        CommandAdded => "command_added" ["name", "response"],
+        CommandUpdated => "command_updated" ["name", "new_name", "response"],
+        CommandDeleted => "command_deleted" ["name"],
+        ImportStarted => "import_started" ["count"],
+        ImportFinished => "import_finished" [],
+        CommandsTitle => "commands_title" [],
+        SearchTitle => "search_title" [],
+        PageFooter => "page_footer" ["page", "pages"],
+        BackupCreated => "backup_created" ["name"],
+        BackupsList => "backups_list" ["backups"],
+        RestoreFinished => "restore_finished" ["name"],
+        TriggerUpdated => "trigger_updated" ["name", "kind", "pattern"],
+        TriggerRemoved => "trigger_removed" ["name"],
+        TriggersEnabled => "triggers_enabled" [],
+        TriggersDisabled => "triggers_disabled" [],
+        ActionsUpdated => "actions_updated" ["name"],
+        ScriptUpdated => "script_updated" ["name", "count"],
+        ScriptRemoved => "script_removed" ["name"],
+        ScriptsCancelled => "scripts_cancelled" ["count"],
+        PlayHelp => "play_help" ["prefix"],
+        FmtUnchanged => "fmt_unchanged" [],
+        ClippyClean => "clippy_clean" [],
+        PlaySuccess => "play_success" [],
+        PlayNoOutput => "play_no_output" [],
+        LanguageCurrent => "language_current" ["language", "languages"],
+        LanguageUpdated => "language_updated" ["language"],
+        LocaleReloaded => "locale_reloaded" ["count"],
+        LocaleReloadedProblems => "locale_reloaded_problems" ["count", "problems"],
+        Pong => "pong" [],
+        BotInfo => "bot_info" ["owner", "invite", "guild"],
+        ShuttingDown => "shutting_down" [],
+        NotAvailable => "not_available" [],
+        TopTitle => "top_title" [],
+        CreatorsTitle => "creators_title" [],
+        CreatorsEntry => "creators_entry" ["commands", "uses", "user"],
+        CommandsCount => "commands_count" ["count"],
+        UsesCount => "uses_count" ["count"],
+        ProfileTitle => "profile_title" ["user"],
+        ProfileCommands => "profile_commands" [],
+        ProfileUses => "profile_uses" [],
+        ProfileRank => "profile_rank" [],
+        ProfileTop => "profile_top" [],
+        StatTitle => "stat_title" ["name"],
+        StatResponse => "stat_response" [],
+        StatUses => "stat_uses" [],
+        StatRank => "stat_rank" [],
+        StatLastUsed => "stat_last_used" [],
+        StatNeverUsed => "stat_never_used" [],
+        StatCreated => "stat_created" [],
+        StatOwner => "stat_owner" [],
+        StatTrigger => "stat_trigger" [],
+        StatEdited => "stat_edited" [],
+        StatEditedValue => "stat_edited_value" ["user", "date"],
+        ClippyLine => "clippy_line" ["line"],
+    }
+}
+
+strings! {
+    /// Errors shown to users
+    Error, "error" {
+        HomeGuild => "home_guild" ["guild"],
+        CommandExists => "command_exists" ["name"],
+        CommandNotFound => "command_not_found" ["name"],
+        CommandReserved => "command_reserved" ["name"],
+        Json => "json" ["error"],
+        ImportExisting => "import_existing" ["count"],
+        ImportReserved => "import_reserved" ["names"],
+        ImportInvalidResponse => "import_invalid_response" ["name"],
+        SearchNoResults => "search_no_results" [],
+        CommandEditNoPermission => "command_edit_no_permission" [],
+        CommandDeleteNoPermission => "command_delete_no_permission" [],
+        CommandTriggerNoPermission => "command_trigger_no_permission" [],
+        CommandActionNoPermission => "command_action_no_permission" [],
+        CommandScriptNoPermission => "command_script_no_permission" [],
+        ProfileNoCommands => "profile_no_commands" ["user"],
+        InvalidInput => "invalid_input" ["error"],
+        Storage => "storage" [],
+        Http => "http" ["error"],
+        Discord => "discord" [],
+        Unsupported => "unsupported" [],
+        Io => "io" [],
+        BackupNotFound => "backup_not_found" ["name"],
+        BackupsEmpty => "backups_empty" [],
+        BackupNotAFile => "backup_not_a_file" ["path"],
+        BackupIntegrity => "backup_integrity" ["error"],
+        BackupMissingColumn => "backup_missing_column" ["column"],
+        DatabasePostgresUnsupported => "database_postgres_unsupported" [],
+        TriggerUnknownKind => "trigger_unknown_kind" ["kind"],
+        TriggerPatternMissing => "trigger_pattern_missing" [],
+        TriggerPatternLength => "trigger_pattern_length" ["max"],
+        TriggerInvalidRegex => "trigger_invalid_regex" ["error"],
+        TriggerNoPermission => "trigger_no_permission" [],
+        TriggerOtherGuild => "trigger_other_guild" [],
+        TriggersNoPermission => "triggers_no_permission" [],
+        InvalidSwitch => "invalid_switch" ["value"],
+        ActionUnknown => "action_unknown" ["action"],
+        ActionTooManyReactions => "action_too_many_reactions" ["max"],
+        ActionDeleteNoPermission => "action_delete_no_permission" [],
+        ActionMissingPermissions => "action_missing_permissions" ["name", "action", "channel"],
+        ActionReact => "action_react" [],
+        ActionDelete => "action_delete" [],
+        ActionDm => "action_dm" [],
+        ActionChannel => "action_channel" [],
+        ActionReply => "action_reply" [],
+        ActionScript => "action_script" [],
+        ScriptLength => "script_length" ["max"],
+        ScriptTextLength => "script_text_length" ["max"],
+        ScriptDelay => "script_delay" ["max"],
+        ScriptTotalDelay => "script_total_delay" ["max"],
+        ScriptTooMany => "script_too_many" ["prefix"],
+        ScriptsNotRunning => "scripts_not_running" [],
+        PlayMissingCodeBlock => "play_missing_code_block" ["prefix"],
+        PlayUnknownFlag => "play_unknown_flag" ["flag", "prefix"],
+        PlayInvalidEdition => "play_invalid_edition" ["edition", "editions"],
+        PlayFailed => "play_failed" [],
+        LanguageGuildOnly => "language_guild_only" [],
+        LanguageNoPermission => "language_no_permission" [],
+        LanguageUnknown => "language_unknown" ["language", "languages"],
+        ShutdownFailed => "shutdown_failed" ["error"],
+        ProfileInvalidUser => "profile_invalid_user" [],
+    }
+}
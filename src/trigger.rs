@@ -0,0 +1,319 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use regex::{RegexBuilder, RegexSet, RegexSetBuilder};
+use serenity::prelude::Context;
+use typemap::Key;
+use error::{Error, Result};
+use store::{CommandStore, CustomCommand, TriggerKind};
+use helpers;
+use strings;
+
+pub const MAX_PATTERN_LENGTH: usize = 200;
+
+// limits on the compiled size of a single regex, so patterns like `(a{100}){100}`
+// are rejected when they are added instead of slowing down every message
+const REGEX_SIZE_LIMIT: usize = 1 << 16;
+const REGEX_DFA_SIZE_LIMIT: usize = 1 << 18;
+
+// triggers are reloaded after this long in case another instance sharing
+// the database changed them
+const TTL_SECS: u64 = 60;
+
+// most commands a single message can trigger, so one message can't make the bot spam
+const MAX_RESPONSES: usize = 3;
+
+/// Checks a pattern for a trigger, returning it as it should be stored
+pub fn validate(kind: TriggerKind, pattern: &str) -> Result<String> {
+    let pattern = pattern.trim();
+
+    if pattern.is_empty() {
+        return Err(Error::invalid_input(helpers::get_error(strings::Error::TriggerPatternMissing)));
+    }
+
+    if pattern.chars().count() > MAX_PATTERN_LENGTH {
+        return Err(Error::invalid_input(
+            helpers::get_error_f(strings::Error::TriggerPatternLength, &[("max", &MAX_PATTERN_LENGTH)])));
+    }
+
+    if kind == TriggerKind::Regex {
+        if let Err(why) = compile(pattern) {
+            return Err(Error::invalid_input(helpers::get_error_f(strings::Error::TriggerInvalidRegex,
+                                                                 &[("error", &why)])));
+        }
+
+        return Ok(pattern.to_string());
+    }
+
+    Ok(pattern.to_lowercase())
+}
+
+/// Whether the trigger of a command can be changed or cleared from a guild, triggers
+/// belong to the guild they were set in since commands are shared between guilds
+pub fn can_change(command: &CustomCommand, guild: Option<u64>) -> bool {
+    command.trigger_guild.is_none() || command.trigger_guild == guild
+}
+
+fn compile(pattern: &str) -> ::std::result::Result<(), ::regex::Error> {
+    try!(RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_DFA_SIZE_LIMIT)
+        .build());
+
+    Ok(())
+}
+
+/// All commands with triggers other than `Prefix`, grouped for matching.
+/// Command names are paired with the guild the trigger was set in.
+struct TriggerSet {
+    exact: HashMap<String, Vec<(u64, String)>>,
+    starts_with: Vec<(String, u64, String)>,
+    contains: Vec<(String, u64, String)>,
+    regexes: Option<RegexSet>,
+    // guilds and command names in the same order as the patterns in `regexes`
+    regex_names: Vec<(u64, String)>,
+    disabled_channels: HashSet<u64>,
+    loaded: Instant
+}
+
+impl TriggerSet {
+    fn load(store: &CommandStore) -> Result<TriggerSet> {
+        let mut exact: HashMap<String, Vec<(u64, String)>> = HashMap::new();
+        let mut starts_with = Vec::new();
+        let mut contains = Vec::new();
+        let mut patterns = Vec::new();
+        let mut regex_names = Vec::new();
+
+        for cmd in try!(store.all()) {
+            // triggers set before they were scoped to a guild have to be set again
+            let (pattern, guild) = match (cmd.pattern, cmd.trigger_guild) {
+                (Some(pattern), Some(guild)) => (pattern, guild),
+                _ => continue,
+            };
+
+            match cmd.trigger {
+                TriggerKind::Prefix => {},
+                TriggerKind::Exact => exact.entry(pattern).or_insert_with(Vec::new).push((guild, cmd.name)),
+                TriggerKind::StartsWith => starts_with.push((pattern, guild, cmd.name)),
+                TriggerKind::Contains => contains.push((pattern, guild, cmd.name)),
+                TriggerKind::Regex => {
+                    // skip patterns that don't compile instead of disabling every regex
+                    if let Err(why) = compile(&pattern) {
+                        warn!("Skipping invalid regex trigger for command {}: {}", cmd.name, why);
+                        continue;
+                    }
+
+                    patterns.push(pattern);
+                    regex_names.push((guild, cmd.name));
+                },
+            }
+        }
+
+        let regexes = if patterns.is_empty() {
+            None
+        } else {
+            let set = RegexSetBuilder::new(&patterns)
+                .case_insensitive(true)
+                .size_limit(REGEX_SIZE_LIMIT * patterns.len())
+                .dfa_size_limit(REGEX_DFA_SIZE_LIMIT * patterns.len())
+                .build();
+
+            match set {
+                Ok(set) => Some(set),
+                Err(why) => {
+                    error!("Failed to compile regex triggers: {}", why);
+                    None
+                },
+            }
+        };
+
+        let disabled_channels = try!(store.disabled_channels()).into_iter().collect();
+
+        Ok(TriggerSet {
+            exact: exact,
+            starts_with: starts_with,
+            contains: contains,
+            regexes: regexes,
+            regex_names: regex_names,
+            disabled_channels: disabled_channels,
+            loaded: Instant::now()
+        })
+    }
+
+    fn is_expired(&self) -> bool {
+        self.loaded.elapsed() >= Duration::from_secs(TTL_SECS)
+    }
+
+    // names of the commands triggered by a message in a guild, most specific triggers first
+    fn matches(&self, guild: u64, content: &str) -> Vec<String> {
+        let lowercase = content.trim().to_lowercase();
+        let mut names = Vec::new();
+
+        if let Some(exact) = self.exact.get(&lowercase) {
+            names.extend(exact.iter().filter(|&&(id, _)| id == guild).map(|&(_, ref name)| name.clone()));
+        }
+
+        for &(ref pattern, id, ref name) in &self.starts_with {
+            if id == guild && lowercase.starts_with(pattern.as_str()) {
+                names.push(name.clone());
+            }
+        }
+
+        for &(ref pattern, id, ref name) in &self.contains {
+            if id == guild && lowercase.contains(pattern.as_str()) {
+                names.push(name.clone());
+            }
+        }
+
+        if let Some(ref regexes) = self.regexes {
+            for i in regexes.matches(content).iter() {
+                let (id, ref name) = self.regex_names[i];
+
+                if id == guild {
+                    names.push(name.clone());
+                }
+            }
+        }
+
+        names.truncate(MAX_RESPONSES);
+
+        names
+    }
+}
+
+/// Compiled triggers, loaded from the store when first needed
+pub struct Triggers {
+    set: RwLock<Option<Arc<TriggerSet>>>
+}
+
+impl Key for Triggers {
+    type Value = Arc<Triggers>;
+}
+
+impl Triggers {
+    pub fn new() -> Triggers {
+        Triggers { set: RwLock::new(None) }
+    }
+
+    /// Drops the compiled triggers so they are reloaded for the next message
+    pub fn invalidate(&self) {
+        *self.set.write().unwrap() = None;
+    }
+
+    fn current(&self, store: &CommandStore) -> Result<Arc<TriggerSet>> {
+        if let Some(ref set) = *self.set.read().unwrap() {
+            if !set.is_expired() {
+                return Ok(set.clone());
+            }
+        }
+
+        let set = Arc::new(try!(TriggerSet::load(store)));
+        *self.set.write().unwrap() = Some(set.clone());
+
+        Ok(set)
+    }
+
+    /// Names of the commands triggered by a message in a channel of a guild
+    pub fn matches(&self, store: &CommandStore, guild: u64, channel: u64, content: &str) -> Result<Vec<String>> {
+        let set = try!(self.current(store));
+
+        if set.disabled_channels.contains(&channel) {
+            return Ok(Vec::new());
+        }
+
+        Ok(set.matches(guild, content))
+    }
+}
+
+/// Gets the triggers without holding the lock on the context data
+pub fn get(ctx: &Context) -> Arc<Triggers> {
+    let data = ctx.data.lock();
+    data.get::<Triggers>().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use store::memory::MemoryStore;
+
+    fn store() -> MemoryStore {
+        let store = MemoryStore::new();
+
+        for &(name, kind, pattern) in &[("hi", TriggerKind::Exact, "hi"),
+                                        ("crab", TriggerKind::Contains, "crab"),
+                                        ("question", TriggerKind::StartsWith, "why"),
+                                        ("number", TriggerKind::Regex, r"\d+")] {
+            store.add(name, "response", 1).unwrap();
+            store.set_trigger(name, kind, Some(pattern), Some(10)).unwrap();
+        }
+
+        store
+    }
+
+    #[test]
+    fn triggers_match_in_their_guild() {
+        let set = TriggerSet::load(&store()).unwrap();
+
+        assert_eq!(set.matches(10, "HI"), vec!["hi"]);
+        assert_eq!(set.matches(10, "why is there a crab"), vec!["question", "crab"]);
+        assert_eq!(set.matches(10, "42"), vec!["number"]);
+        assert!(set.matches(10, "hello").is_empty());
+    }
+
+    #[test]
+    fn triggers_dont_match_in_other_guilds() {
+        let set = TriggerSet::load(&store()).unwrap();
+
+        for content in &["hi", "why is there a crab", "42"] {
+            assert!(set.matches(20, content).is_empty(), "{} matched in another guild", content);
+        }
+    }
+
+    #[test]
+    fn triggers_without_a_guild_are_skipped() {
+        let store = store();
+        store.add("old", "response", 1).unwrap();
+        store.set_trigger("old", TriggerKind::Contains, Some("old"), None).unwrap();
+
+        let set = TriggerSet::load(&store).unwrap();
+        assert!(set.matches(10, "old").is_empty());
+    }
+
+    #[test]
+    fn disabled_channels_dont_match() {
+        let store = store();
+        store.set_channel_triggers(5, false).unwrap();
+
+        let triggers = Triggers::new();
+        assert!(triggers.matches(&store, 10, 5, "hi").unwrap().is_empty());
+        assert_eq!(triggers.matches(&store, 10, 6, "hi").unwrap(), vec!["hi"]);
+    }
+
+    #[test]
+    fn other_guilds_cant_change_triggers() {
+        let store = store();
+        let cmd = store.get("crab").unwrap();
+
+        assert!(can_change(&cmd, Some(10)));
+        assert!(!can_change(&cmd, Some(20)));
+        assert!(!can_change(&cmd, None));
+
+        // a command without a trigger can be given one anywhere
+        store.set_trigger("crab", TriggerKind::Prefix, None, None).unwrap();
+        let cmd = store.get("crab").unwrap();
+        assert!(can_change(&cmd, Some(20)));
+        assert!(can_change(&cmd, None));
+    }
+
+    #[test]
+    fn validate_limits_patterns() {
+        assert_eq!(validate(TriggerKind::Contains, "  Crab ").unwrap(), "crab");
+        assert_eq!(validate(TriggerKind::Regex, "^Crab").unwrap(), "^Crab");
+
+        assert!(validate(TriggerKind::Exact, "  ").is_err());
+        assert!(validate(TriggerKind::Exact, &"a".repeat(MAX_PATTERN_LENGTH + 1)).is_err());
+        assert!(validate(TriggerKind::Regex, "(").is_err());
+        assert!(validate(TriggerKind::Regex, "(a{100}){100}").is_err());
+    }
+}